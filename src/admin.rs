@@ -0,0 +1,89 @@
+use crate::metrics;
+use crate::status::StatusPing;
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::body::Incoming;
+use hyper::header::CONTENT_TYPE;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::oneshot;
+
+/// Serves `/metrics` and `/health` on `addr`. Returns the address actually bound, for port 0.
+pub async fn serve(
+    addr: &str,
+    status_tx: UnboundedSender<StatusPing>,
+) -> std::io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr).await?;
+    let local_addr = listener.local_addr()?;
+
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(err) => {
+                    error!(%err, "Error on accepting admin connection.");
+                    continue;
+                }
+            };
+            let status_tx = status_tx.clone();
+            tokio::spawn(async move {
+                let service = service_fn(move |req: Request<Incoming>| {
+                    let status_tx = status_tx.clone();
+                    async move { Ok::<_, Infallible>(respond(req, status_tx).await) }
+                });
+                if let Err(err) = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await
+                {
+                    debug!(%err, "Error on admin connection.");
+                }
+            });
+        }
+    });
+
+    Ok(local_addr)
+}
+
+async fn respond(
+    req: Request<Incoming>,
+    status_tx: UnboundedSender<StatusPing>,
+) -> Response<Full<Bytes>> {
+    match req.uri().path() {
+        "/metrics" => response(StatusCode::OK, "text/plain", metrics::render()),
+        "/health" => {
+            let (report_tx, report_rx) = oneshot::channel();
+            let _ = status_tx.send(StatusPing::Report(report_tx));
+            match report_rx.await {
+                Ok(report) => response(
+                    if report.healthy() {
+                        StatusCode::OK
+                    } else {
+                        StatusCode::SERVICE_UNAVAILABLE
+                    },
+                    "application/json",
+                    serde_json::to_string(&report).unwrap(),
+                ),
+                Err(_) => response(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "text/plain",
+                    "Status loop not running.".to_owned(),
+                ),
+            }
+        }
+        _ => response(StatusCode::NOT_FOUND, "text/plain", "Not found.".to_owned()),
+    }
+}
+
+fn response(status: StatusCode, content_type: &str, body: String) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, content_type)
+        .body(Full::new(Bytes::from(body)))
+        .unwrap()
+}
@@ -0,0 +1,173 @@
+use crate::health::{Connection, Health};
+use chrono::prelude::*;
+use std::time::Duration;
+
+/// When to post alerts to the notify channel. See `ALERTS` in `conf.rs` for defaults.
+#[derive(Clone, Copy)]
+pub struct AlertConfig {
+    /// Alert when there has been no signup for this long, during `expected_hours`.
+    pub no_signups_for: Duration,
+    /// UTC hours `(from, to)` during which signups are expected, `to` excluded. `(0, 24)` is
+    /// always; `(22, 6)` wraps around midnight.
+    pub expected_hours: (u32, u32),
+    /// Alert when a connection reconnects more than this often within `reconnects_window`.
+    pub max_reconnects: usize,
+    pub reconnects_window: Duration,
+    /// Alert when more than this percentage of the actions within `actions_window` failed, if
+    /// there were at least `min_actions`.
+    pub max_action_failure_percent: usize,
+    pub min_actions: usize,
+    pub actions_window: Duration,
+    /// Alert when there are more than this many Lua errors within `lua_errors_window`.
+    pub max_lua_errors: usize,
+    pub lua_errors_window: Duration,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum AlertKind {
+    QuietStream,
+    NoSignups,
+    Reconnects(Connection),
+    ActionFailures,
+    LuaErrors,
+}
+
+impl AlertKind {
+    fn name(&self) -> &'static str {
+        match self {
+            AlertKind::QuietStream => "lichess event stream silence",
+            AlertKind::NoSignups => "no signups",
+            AlertKind::Reconnects(Connection::Lichess) => "lichess event stream reconnects",
+            AlertKind::Reconnects(Connection::Chat) => "chat reconnects",
+            AlertKind::ActionFailures => "action failures",
+            AlertKind::LuaErrors => "Lua errors",
+        }
+    }
+}
+
+/// Tracks which alerts are firing, so that each is posted once when it starts and once when
+/// it recovers.
+pub struct Alerts {
+    config: AlertConfig,
+    /// The event stream is quiet once silent for this long, see `StatusConfig`.
+    stream_timeout: Duration,
+    firing: Vec<AlertKind>,
+}
+
+impl Alerts {
+    pub fn new(config: AlertConfig, stream_timeout: Duration) -> Alerts {
+        Alerts {
+            config,
+            stream_timeout,
+            firing: vec![],
+        }
+    }
+
+    fn expected_hour(&self, hour: u32) -> bool {
+        let (from, to) = self.config.expected_hours;
+        if from <= to {
+            hour >= from && hour < to
+        } else {
+            hour >= from || hour < to
+        }
+    }
+
+    fn conditions(&self, health: &Health) -> Vec<(AlertKind, Option<String>)> {
+        let c = &self.config;
+        let mut conditions = vec![];
+
+        let stream_silence = health.since_stream_data();
+        conditions.push((
+            AlertKind::QuietStream,
+            if stream_silence > self.stream_timeout {
+                Some(format!(
+                    "no data on the lichess event stream for {} seconds, restarted the watcher.",
+                    stream_silence.as_secs()
+                ))
+            } else {
+                None
+            },
+        ));
+
+        let silence = health.since_last_signup();
+        conditions.push((
+            AlertKind::NoSignups,
+            if silence > c.no_signups_for && self.expected_hour(Utc::now().hour()) {
+                Some(format!(
+                    "no signups for {} minutes.",
+                    silence.as_secs() / 60
+                ))
+            } else {
+                None
+            },
+        ));
+
+        for connection in &[Connection::Lichess, Connection::Chat] {
+            let reconnects = health.reconnects_within(*connection, c.reconnects_window);
+            let kind = AlertKind::Reconnects(*connection);
+            conditions.push((
+                kind,
+                if reconnects > c.max_reconnects {
+                    Some(format!(
+                        "{} {} in the last {} minutes.",
+                        reconnects,
+                        kind.name(),
+                        c.reconnects_window.as_secs() / 60
+                    ))
+                } else {
+                    None
+                },
+            ));
+        }
+
+        let (ok, failed) = health.action_results_within(c.actions_window);
+        let total = ok + failed;
+        conditions.push((
+            AlertKind::ActionFailures,
+            if total >= c.min_actions
+                && total > 0
+                && failed * 100 > c.max_action_failure_percent * total
+            {
+                Some(format!("{} of the last {} actions failed.", failed, total))
+            } else {
+                None
+            },
+        ));
+
+        let lua_errors = health.lua_errors_within(c.lua_errors_window);
+        conditions.push((
+            AlertKind::LuaErrors,
+            if lua_errors > c.max_lua_errors {
+                Some(format!(
+                    "{} Lua errors in the last {} seconds.",
+                    lua_errors,
+                    c.lua_errors_window.as_secs()
+                ))
+            } else {
+                None
+            },
+        ));
+
+        conditions
+    }
+
+    /// Messages for the alerts that started or recovered since the last check.
+    pub fn check(&mut self, health: &Health) -> Vec<String> {
+        let mut messages = vec![];
+        for (kind, description) in self.conditions(health) {
+            let was_firing = self.firing.contains(&kind);
+            match description {
+                Some(description) if !was_firing => {
+                    self.firing.push(kind);
+                    messages.push(format!("Alert: {}", description));
+                }
+                None if was_firing => {
+                    self.firing.retain(|k| *k != kind);
+                    messages.push(format!("Resolved: {}.", kind.name()));
+                }
+                _ => {}
+            }
+        }
+        messages
+    }
+}
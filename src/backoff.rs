@@ -0,0 +1,69 @@
+use rand::{thread_rng, Rng};
+use std::time::Duration;
+
+/// How long to wait before reconnecting: `initial`, doubled after each failed attempt up to
+/// `max`, minus up to `jitter` (a fraction, clamped to `0.0..=1.0`) of it at random. A
+/// connection that stayed up for `stable_after` starts over from `initial`.
+#[derive(Clone, Copy)]
+pub struct BackoffPolicy {
+    pub initial: Duration,
+    pub max: Duration,
+    pub jitter: f64,
+    pub stable_after: Duration,
+}
+
+/// How long to wait before retrying a request, doubled as for `BackoffPolicy`, but never
+/// starting over.
+#[derive(Clone, Copy)]
+pub struct RetryPolicy {
+    pub initial: Duration,
+    pub max: Duration,
+    pub jitter: f64,
+}
+
+pub struct Backoff {
+    policy: BackoffPolicy,
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new(policy: BackoffPolicy) -> Backoff {
+        Backoff { policy, attempt: 0 }
+    }
+
+    pub fn retrying(policy: RetryPolicy) -> Backoff {
+        Backoff::new(BackoffPolicy {
+            initial: policy.initial,
+            max: policy.max,
+            jitter: policy.jitter,
+            stable_after: Duration::MAX,
+        })
+    }
+
+    /// The delay before the next attempt, given how long the last connection ran for.
+    pub fn next_delay(&mut self, ran_for: Duration) -> Duration {
+        if ran_for >= self.policy.stable_after {
+            self.attempt = 0;
+        }
+        self.next_retry_delay()
+    }
+
+    /// The delay before the next retry of something that never runs for long, like a request.
+    /// It never starts over from `initial`.
+    pub fn next_retry_delay(&mut self) -> Duration {
+        let base = self
+            .policy
+            .initial
+            .checked_mul(2u32.saturating_pow(self.attempt))
+            .map_or(self.policy.max, |d| d.min(self.policy.max));
+        self.attempt = self.attempt.saturating_add(1);
+
+        let max_jitter = self.policy.jitter.clamp(0.0, 1.0);
+        let jitter = if max_jitter > 0.0 {
+            thread_rng().gen_range(0.0, max_jitter)
+        } else {
+            0.0
+        };
+        base.mul_f64(1.0 - jitter)
+    }
+}
@@ -0,0 +1,881 @@
+use crate::chat::parser::{parse_error, suggest, ParseError, Parser, Token, TokenKind};
+use crate::chat::ButtonValue;
+use crate::event::{Email, Event, Ip, User};
+use crate::logging;
+use crate::signup::rules::{
+    Action, Collision, Criterion, Delay, Exemption, Rule, RuleEdit, ACTION_KEYS, MAX_DELAY_MS,
+    RATING_PERFS, REPORT_REASONS, WARNING_PRESETS,
+};
+use chrono::{DateTime, Duration, Utc};
+use regex::Regex;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// A parsed command.
+pub enum UserCommand {
+    /// Handled by the event loop.
+    Event(Box<Event>),
+    /// Shows the log level, or sets it to the given directives.
+    LogLevel(Option<String>),
+    /// A script to run, like `./restart`.
+    External(&'static str),
+    /// Explains the commands starting with the given words.
+    Help(String),
+}
+
+/// The words naming a command, the syntax of its arguments, and what `help` says about it.
+pub struct CommandDef {
+    pub words: &'static str,
+    pub args: &'static str,
+    pub summary: &'static str,
+    /// Empty if the syntax says it all, or the command needs a code block.
+    pub example: &'static str,
+}
+
+impl CommandDef {
+    /// The words and the arguments, on one line.
+    pub fn syntax(&self) -> String {
+        let mut syntax = self.words.to_owned();
+        for arg in self.args.split_whitespace() {
+            syntax.push(' ');
+            syntax.push_str(arg);
+        }
+        syntax
+    }
+}
+
+pub const COMMANDS: &[CommandDef] = &[
+    CommandDef {
+        words: "status",
+        args: "",
+        summary: "Posts the health report.",
+        example: "status",
+    },
+    CommandDef {
+        words: "help",
+        args: "[command]",
+        summary: "Lists the commands, or explains the ones starting with `command`.",
+        example: "help signup rules add",
+    },
+    CommandDef {
+        words: "log level",
+        args: "[directives]",
+        summary: "Shows the log level, or sets it to `EnvFilter` directives.",
+        example: "log level info,lichess_event_stream::eventstream=debug",
+    },
+    CommandDef {
+        words: "upgrade",
+        args: "",
+        summary: "Runs `./upgrade`.",
+        example: "",
+    },
+    CommandDef {
+        words: "restart",
+        args: "",
+        summary: "Runs `./restart`.",
+        example: "",
+    },
+    CommandDef {
+        words: "signup rules add",
+        args: "<name> if|if_susp_ip <criterion> then <actions>|none [nodelay] \
+               [delay <delay>] [delay <action>=<delay>] [weight <n>]",
+        summary: "Adds a rule. Its actions are sent to the users it catches, and its weight counts towards score criteria.",
+        example: "signup rules add raiders if username regex \"^raid\\d+$\" then shadowban+close delay 30-180",
+    },
+    CommandDef {
+        words: "signup rules show",
+        args: "<name>",
+        summary: "Shows a rule and its statistics.",
+        example: "signup rules show raiders",
+    },
+    CommandDef {
+        words: "signup rules remove",
+        args: "<name>",
+        summary: "Removes a rule.",
+        example: "signup rules remove raiders",
+    },
+    CommandDef {
+        words: "signup rules disable-re",
+        args: "<regex>",
+        summary: "Disables the rules whose names match the regex.",
+        example: "signup rules disable-re ^raid",
+    },
+    CommandDef {
+        words: "signup rules enable-re",
+        args: "<regex>",
+        summary: "Enables the rules whose names match the regex.",
+        example: "signup rules enable-re ^raid",
+    },
+    CommandDef {
+        words: "signup rules list",
+        args: "",
+        summary: "Lists the rules.",
+        example: "",
+    },
+    CommandDef {
+        words: "signup rules edit",
+        args: "<name> set actions|criterion|nodelay|susp_ip|weight|delay <value>, \
+               or <name> rename <new name>",
+        summary: "Changes a rule in place, keeping its statistics.",
+        example: "signup rules edit raiders set delay default",
+    },
+    CommandDef {
+        words: "signup rules export",
+        args: "[regex]",
+        summary: "Posts the rules whose names match the regex, or all rules, as a JSON file.",
+        example: "signup rules export ^raid",
+    },
+    CommandDef {
+        words: "signup rules import",
+        args: "[--apply] [--replace|--rename] ```rules JSON```",
+        summary: "Previews importing rules, or imports them with `--apply`. Rules whose name is taken are skipped, replaced or renamed.",
+        example: "",
+    },
+    CommandDef {
+        words: "signup rules stats",
+        args: "",
+        summary: "Lists the precision, matches per day and last match of every rule.",
+        example: "",
+    },
+    CommandDef {
+        words: "signup rules false-positive",
+        args: "<rule> <username>",
+        summary: "Marks a user caught by a rule as wrongly caught.",
+        example: "signup rules false-positive raiders Raidmaster",
+    },
+    CommandDef {
+        words: "signup rules test",
+        args: "```user JSON```",
+        summary: "Runs the rules on a made-up signup, without sending actions.",
+        example: "",
+    },
+    CommandDef {
+        words: "signup exemptions add",
+        args: "<name> if <criterion> [for <duration>]",
+        summary: "Exempts the users matching the criterion from all rules.",
+        example: "signup exemptions add streamer if username contains streamer for 7d",
+    },
+    CommandDef {
+        words: "signup exemptions remove",
+        args: "<name>",
+        summary: "Removes an exemption.",
+        example: "signup exemptions remove streamer",
+    },
+    CommandDef {
+        words: "signup exemptions list",
+        args: "",
+        summary: "Lists the exemptions.",
+        example: "",
+    },
+    CommandDef {
+        words: "signup revert",
+        args: "<username>",
+        summary: "Cancels the pending actions on a user, and reverts the sent ones that can be undone.",
+        example: "signup revert TheRaider",
+    },
+    CommandDef {
+        words: "signup revert-rule",
+        args: "<name> [--since <duration>]",
+        summary: "Cancels and reverts the actions of a rule, on everyone it caught.",
+        example: "signup revert-rule raiders --since 2h",
+    },
+];
+
+/// Criteria, with their checks. Lua takes its code right after `lua`.
+pub const CRITERIA: &[(&str, &[&str])] = &[
+    ("ip", &["equals"]),
+    ("email", &["contains", "regex"]),
+    ("username", &["contains", "regex"]),
+    ("useragent", &["length-lte"]),
+    ("lua", &[]),
+    ("score", &["gte", "range"]),
+];
+
+pub fn handle_command(
+    command: String,
+    tx: UnboundedSender<Event>,
+) -> Result<Option<String>, ParseError> {
+    match parse_command(&command)? {
+        // The event handler stops receiving once it is shutting down.
+        UserCommand::Event(event) => match tx.send(*event) {
+            Ok(_) => Ok(None),
+            Err(_) => Ok(Some("The bot is shutting down.".to_owned())),
+        },
+        UserCommand::LogLevel(level) => Ok(Some(handle_log_level(level))),
+        UserCommand::External(command) => handle_external_command(command),
+        UserCommand::Help(words) => Ok(Some(help(&words))),
+    }
+}
+
+pub fn parse_command(command: &str) -> Result<UserCommand, ParseError> {
+    let mut p = Parser::new(command)?;
+    let def = parse_words(&mut p)?;
+    parse_args(def, &mut p).map_err(|mut err| {
+        err.message += &format!("\nUsage: {}", inline_code(&def.syntax()));
+        err
+    })
+}
+
+/// The words that can follow `words` in a command.
+pub fn subcommands(words: &str) -> Vec<&'static str> {
+    let mut next = vec![];
+    for def in COMMANDS {
+        let rest = match words {
+            "" => Some(def.words),
+            _ => def
+                .words
+                .strip_prefix(words)
+                .and_then(|rest| rest.strip_prefix(' ')),
+        };
+        if let Some(word) = rest.and_then(|rest| rest.split(' ').next()) {
+            if !next.contains(&word) {
+                next.push(word);
+            }
+        }
+    }
+    next
+}
+
+/// Reads the words naming a command.
+fn parse_words(p: &mut Parser) -> Result<&'static CommandDef, ParseError> {
+    let mut words = String::new();
+    loop {
+        if let Some(def) = COMMANDS.iter().find(|def| def.words == words) {
+            return Ok(def);
+        }
+        let next = subcommands(&words);
+        let of = match words.as_str() {
+            "" => "".to_owned(),
+            words => format!(" of `{}`", words),
+        };
+        match p.peek() {
+            Some(token) if next.contains(&token.text.as_str()) => {
+                if !words.is_empty() {
+                    words.push(' ');
+                }
+                words.push_str(&token.text);
+                p.next("a command")?;
+            }
+            Some(token) => {
+                return Err(p.error_at(
+                    token,
+                    &format!(
+                        "Could not parse user command: no command `{}`{}, {} See `help`.",
+                        token.text,
+                        of,
+                        suggest(&token.text, &next)
+                    ),
+                ))
+            }
+            None => {
+                return Err(p.error_at_next(&format!(
+                    "Could not parse user command: missing command{}, use one of: {}",
+                    of,
+                    next.join(", ")
+                )))
+            }
+        }
+    }
+}
+
+fn parse_args(def: &CommandDef, p: &mut Parser) -> Result<UserCommand, ParseError> {
+    let event = match def.words {
+        "status" => Event::InternalSlackStatusCommand,
+        "help" => {
+            let mut words = vec![];
+            while !p.at_end() {
+                words.push(p.next("a command")?.text);
+            }
+            return Ok(UserCommand::Help(words.join(" ")));
+        }
+        "log level" => {
+            let level = match p.at_end() {
+                true => None,
+                false => Some(p.next("log directives")?.text),
+            };
+            p.end()?;
+            return Ok(UserCommand::LogLevel(level));
+        }
+        "upgrade" => {
+            p.end()?;
+            return Ok(UserCommand::External("./upgrade"));
+        }
+        "restart" => {
+            p.end()?;
+            return Ok(UserCommand::External("./restart"));
+        }
+        "signup rules add" => Event::InternalAddRule {
+            rule: parse_rule(p)?,
+        },
+        "signup rules show" => Event::InternalShowRule(p.next("a rule name")?.text),
+        "signup rules remove" => Event::InternalRemoveRule(p.next("a rule name")?.text),
+        "signup rules disable-re" => Event::InternalDisableRules(p.next("a regex")?.text),
+        "signup rules enable-re" => Event::InternalEnableRules(p.next("a regex")?.text),
+        "signup rules list" => Event::InternalListRules,
+        "signup rules edit" => Event::InternalEditRule {
+            name: p.next("a rule name")?.text,
+            edit: parse_edit(p)?,
+        },
+        "signup rules export" => {
+            let pattern = match p.at_end() {
+                true => None,
+                false => Some(p.next("a regex")?.text).filter(|p| !p.is_empty()),
+            };
+            Event::InternalExportRules(pattern)
+        }
+        "signup rules import" => parse_import(p)?,
+        "signup rules stats" => Event::InternalRuleStats,
+        "signup rules false-positive" => Event::InternalMarkFalsePositive {
+            rule: p.next("a rule name")?.text,
+            username: p.next("a username")?.text,
+        },
+        "signup rules test" => {
+            Event::InternalHypotheticalSignup(p.parse_next("a user as JSON", parse_user)?)
+        }
+        "signup exemptions add" => Event::InternalAddExemption {
+            exemption: parse_exemption(p)?,
+        },
+        "signup exemptions remove" => Event::InternalRemoveExemption(p.next("a name")?.text),
+        "signup exemptions list" => Event::InternalListExemptions,
+        "signup revert" => Event::InternalRevertUser(p.next("a username")?.text),
+        "signup revert-rule" => {
+            let rule = p.next("a rule name")?.text;
+            let since = match p.keyword_arg("--since")? {
+                Some(value) => Some(p.parse_token(&value, |v| ago(parse_duration(v)?))?),
+                None => None,
+            };
+            Event::InternalRevertRule { rule, since }
+        }
+        _ => return Err(parse_error(None)),
+    };
+    p.end()?;
+    Ok(UserCommand::Event(Box::new(event)))
+}
+
+/// Handles a click on one of `chat::MATCH_BUTTONS`.
+pub fn handle_button(
+    action_id: &str,
+    value: &str,
+    tx: UnboundedSender<Event>,
+) -> Result<Option<String>, ParseError> {
+    let ButtonValue { rule, username } = serde_json::from_str(value)?;
+    let event = match action_id {
+        "undo_actions" => Event::InternalRevertUser(username),
+        "disable_rule" => Event::InternalDisableRules(format!("^{}$", regex::escape(&rule))),
+        "exempt_user" => Event::InternalAddExemption {
+            exemption: Exemption {
+                name: format!("user-{}", username.to_lowercase()),
+                criterion: Criterion::UsernameRegex(Regex::new(&format!(
+                    "(?i)^{}$",
+                    regex::escape(&username)
+                ))?),
+                expires: None,
+                match_count: 0,
+            },
+        },
+        "false_positive" => Event::InternalMarkFalsePositive { rule, username },
+        _ => {
+            return Err(parse_error(Some(&format!(
+                "Unknown button `{}`",
+                action_id
+            ))))
+        }
+    };
+    match tx.send(event) {
+        Ok(_) => Ok(None),
+        Err(_) => Ok(Some("The bot is shutting down.".to_owned())),
+    }
+}
+
+fn handle_log_level(level: Option<String>) -> String {
+    match level {
+        None => match logging::level() {
+            Some(level) => format!("Log level: `{}`.", level),
+            None => "Logging is not initialized.".to_owned(),
+        },
+        Some(level) => match logging::set_level(&level) {
+            Ok(_) => {
+                info!(level = %level, "Log level changed.");
+                format!("Log level set to `{}`.", level)
+            }
+            Err(err) => err,
+        },
+    }
+}
+
+/// The syntax of the commands starting with `words`, with the details of a single one.
+fn help(words: &str) -> String {
+    let defs: Vec<&CommandDef> = COMMANDS
+        .iter()
+        .filter(|def| def.words == words || def.words.starts_with(&format!("{} ", words)))
+        .collect();
+    if words.is_empty() || defs.len() > 1 {
+        let defs = if !defs.is_empty() {
+            defs
+        } else {
+            COMMANDS.iter().collect()
+        };
+        let mut lines: Vec<String> = defs
+            .iter()
+            .map(|def| format!("`{}`: {}", def.words, def.summary))
+            .collect();
+        lines.push("Use `help <command>` for its syntax, like `help signup rules add`.".to_owned());
+        return lines.join("\n");
+    }
+    let def = match defs.first() {
+        Some(def) => def,
+        None => {
+            let known: Vec<&str> = COMMANDS.iter().map(|def| def.words).collect();
+            return format!("No command `{}`, {}", words, suggest(words, &known));
+        }
+    };
+
+    let mut lines = vec![inline_code(&def.syntax()), def.summary.to_owned()];
+    if !def.example.is_empty() {
+        lines.push(format!("Example: {}", inline_code(def.example)));
+    }
+    if def.args.contains("criterion") {
+        let criteria: Vec<String> = CRITERIA
+            .iter()
+            .map(|(element, checks)| match checks.len() {
+                0 => format!("`{} <code>`", element),
+                _ => format!("`{} {} <value>`", element, checks.join("|")),
+            })
+            .collect();
+        lines.push(format!("Criteria: {}", criteria.join(", ")));
+    }
+    if def.args.contains("actions") {
+        let actions: Vec<String> = ACTION_KEYS
+            .iter()
+            .map(|key| format!("`{}`", action_syntax(key)))
+            .collect();
+        lines.push(format!("Actions, joined with `+`: {}", actions.join(", ")));
+    }
+    if def.args.contains("delay") {
+        lines
+            .push("Delays: `none`, seconds like `10`, or a random range like `30-180`.".to_owned());
+    }
+    if def.args.contains("duration") {
+        lines.push("Durations: like `30m`, `12h`, `7d` or `2w`.".to_owned());
+    }
+    lines.join("\n")
+}
+
+/// `text` as inline code, unless it has code spans of its own.
+fn inline_code(text: &str) -> String {
+    match text.contains('`') {
+        true => text.to_owned(),
+        false => format!("`{}`", text),
+    }
+}
+
+/// The syntax of the action `key`, as `parse_action` reads it.
+fn action_syntax(key: &str) -> String {
+    match key {
+        "note" => "note[:<text>]".to_owned(),
+        "warn" => {
+            let presets: Vec<&str> = WARNING_PRESETS.iter().map(|(k, _)| *k).collect();
+            format!("warn:{}", presets.join("|"))
+        }
+        "report" => format!("report:{}", REPORT_REASONS.join("|")),
+        "alt" => "alt:<main username>".to_owned(),
+        "reportban" => "reportban[:off]".to_owned(),
+        "resetrating" => format!("resetrating:{}", RATING_PERFS.join("|")),
+        key => key.to_owned(),
+    }
+}
+
+/// Parses what follows `rules add`.
+fn parse_rule(p: &mut Parser) -> Result<Rule, ParseError> {
+    let name = p.next("a rule name")?.text;
+    let susp_ip = p.one_of("condition", &["if", "if_susp_ip", "if_ip_susp"])? != "if";
+    let criterion = parse_criterion(p)?;
+    p.keyword("then")?;
+    let actions = p.parse_next("actions", parse_actions)?;
+
+    let mut no_delay = false;
+    let mut weight = 0;
+    let mut delay = None;
+    let mut action_delays = vec![];
+    while let Some(token) = p.peek().cloned() {
+        let conflict = "`nodelay` and `delay` can't be used together";
+        if p.flag("nodelay") {
+            if delay.is_some() || !action_delays.is_empty() {
+                return Err(p.error_at(&token, conflict));
+            }
+            no_delay = true;
+        } else if let Some(value) = p.keyword_arg("delay")? {
+            if no_delay {
+                return Err(p.error_at(&token, conflict));
+            }
+            match value.text.find('=') {
+                Some(i) => {
+                    let key = value.text[..i].to_owned();
+                    if !ACTION_KEYS.contains(&key.as_str()) {
+                        let message = format!(
+                            "Unknown action `{}` in delay, {}",
+                            key,
+                            suggest(&key, ACTION_KEYS)
+                        );
+                        return Err(p.error_at(&value, &message));
+                    }
+                    let action_delay = p.parse_token(&value, |v| parse_delay(&v[i + 1..]))?;
+                    action_delays.push((key, action_delay));
+                }
+                None => delay = Some(p.parse_token(&value, parse_delay)?),
+            }
+        } else if let Some(value) = p.keyword_arg("weight")? {
+            weight = p.parse_token(&value, |v| Ok(v.parse()?))?;
+        } else {
+            return Err(p.error_at(
+                &token,
+                &format!(
+                    "Unknown option `{}`, {}",
+                    token.text,
+                    suggest(&token.text, &["nodelay", "delay", "weight"])
+                ),
+            ));
+        }
+    }
+
+    if actions.is_empty() && weight == 0 {
+        return Err(parse_error(Some("A rule without actions needs a weight")));
+    }
+
+    Ok(Rule {
+        name,
+        criterion,
+        actions,
+        match_count: 0,
+        most_recent_caught: vec![],
+        no_delay,
+        enabled: true,
+        susp_ip,
+        weight,
+        delay,
+        action_delays,
+        false_positives: vec![],
+        first_match: None,
+        last_match: None,
+        changes: vec![],
+    })
+}
+
+/// Parses what follows `exemptions add`.
+fn parse_exemption(p: &mut Parser) -> Result<Exemption, ParseError> {
+    let name = p.next("a name")?.text;
+    p.keyword("if")?;
+    let start = p.peek().cloned();
+    let criterion = parse_criterion(p)?;
+    if criterion.is_score() {
+        let message = "Exemptions can't use score criteria";
+        return Err(match start {
+            Some(token) => p.error_at(&token, message),
+            None => parse_error(Some(message)),
+        });
+    }
+
+    let expires = match p.keyword_arg("for")? {
+        Some(value) => Some(p.parse_token(&value, |v| from_now(parse_duration(v)?))?),
+        None => None,
+    };
+
+    Ok(Exemption {
+        name,
+        criterion,
+        expires,
+        match_count: 0,
+    })
+}
+
+/// Parses a criterion like `username contains raid`, `score range 2..5` or `lua <code>`.
+fn parse_criterion(p: &mut Parser) -> Result<Criterion, ParseError> {
+    let element = p.next("a criterion")?;
+    if element.text == "print" {
+        return Err(p.error_at(&element, "Use lichess print ban instead"));
+    }
+    let checks = match CRITERIA.iter().find(|(e, _)| *e == element.text) {
+        Some((_, checks)) => *checks,
+        None => {
+            let elements: Vec<&str> = CRITERIA.iter().map(|(e, _)| *e).collect();
+            return Err(p.error_at(
+                &element,
+                &format!(
+                    "Unknown criterion `{}`, {}",
+                    element.text,
+                    suggest(&element.text, &elements)
+                ),
+            ));
+        }
+    };
+    if element.text == "lua" {
+        return Ok(Criterion::Lua(p.next("Lua code")?.text));
+    }
+
+    let check = p.one_of(&format!("{} check", element.text), checks)?;
+    let what = format!("a value for `{} {}`", element.text, check);
+    p.parse_next(&what, |value| {
+        let value = value.to_owned();
+        Ok(match (element.text.as_str(), check) {
+            ("ip", _) => Criterion::IpMatch(Ip(value)),
+            ("email", "contains") => Criterion::EmailContains(value),
+            ("email", _) => Criterion::EmailRegex(Regex::new(&value)?),
+            ("username", "contains") => Criterion::UsernameContains(value),
+            ("username", _) => Criterion::UsernameRegex(Regex::new(&value)?),
+            ("useragent", _) => Criterion::UseragentLengthLte(value.parse()?),
+            ("score", "gte") => Criterion::ScoreRange(value.parse()?, None),
+            _ => {
+                let bounds: Vec<&str> = value.split("..").collect();
+                if bounds.len() != 2 {
+                    return Err(parse_error(Some("Score ranges look like `min..max`")));
+                }
+                let (min, max) = (bounds[0].parse()?, bounds[1].parse()?);
+                if min > max {
+                    return Err(parse_error(Some("Score range minimum exceeds its maximum")));
+                }
+                Criterion::ScoreRange(min, Some(max))
+            }
+        })
+    })
+}
+
+/// Parses the edit in `edit <name> set <field> <value>` or `edit <name> rename <new name>`.
+fn parse_edit(p: &mut Parser) -> Result<RuleEdit, ParseError> {
+    if p.one_of("edit", &["set", "rename"])? == "rename" {
+        return Ok(RuleEdit::Rename(p.next("the new name")?.text));
+    }
+    let on_off = |value: &str| match value {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(parse_error(Some("Use `on` or `off`"))),
+    };
+    let field = p.one_of(
+        "field",
+        &[
+            "actions",
+            "criterion",
+            "nodelay",
+            "susp_ip",
+            "weight",
+            "delay",
+        ],
+    )?;
+    Ok(match field {
+        "actions" => RuleEdit::Actions(p.parse_next("actions", parse_actions)?),
+        "criterion" => RuleEdit::Criterion(parse_criterion(p)?),
+        "nodelay" => RuleEdit::NoDelay(p.parse_next("`on` or `off`", on_off)?),
+        "susp_ip" => RuleEdit::SuspIp(p.parse_next("`on` or `off`", on_off)?),
+        "weight" => RuleEdit::Weight(p.parse_next("a weight", |v| Ok(v.parse()?))?),
+        _ => RuleEdit::Delay(p.parse_next("a delay", |v| match v {
+            "default" => Ok(None),
+            v => parse_delay(v).map(Some),
+        })?),
+    })
+}
+
+/// Parses what follows `rules import`: options, and the rules as JSON in a code span.
+fn parse_import(p: &mut Parser) -> Result<Event, ParseError> {
+    let mut apply = false;
+    let mut on_collision = Collision::Skip;
+    let mut code: Option<Token> = None;
+    while !p.at_end() {
+        let token = p.next("an import option")?;
+        match token.text.as_str() {
+            "--apply" => apply = true,
+            "--replace" => on_collision = Collision::Replace,
+            "--rename" => on_collision = Collision::Rename,
+            _ if token.kind == TokenKind::Code && code.is_none() => code = Some(token),
+            _ => return Err(p.error_at(&token, &format!("Unknown import option `{}`", token.text))),
+        }
+    }
+    let code = match code {
+        Some(code) => code,
+        None => return Err(p.error_at_next("Paste the rules to import as a ```code block```")),
+    };
+    let rules = p.parse_token(&code, |code| {
+        let parsed = if code.starts_with('[') {
+            serde_json::from_str(code)
+        } else {
+            serde_json::from_str(code).map(|rule| vec![rule])
+        };
+        parsed.map_err(|err| parse_error(Some(&format!("Invalid rules JSON: {}", err))))
+    })?;
+    Ok(Event::InternalImportRules {
+        rules,
+        on_collision,
+        apply,
+    })
+}
+
+/// Parses a signup for `rules test`, where Slack links the email like `<mailto:a@b.c|a@b.c>`.
+fn parse_user(json: &str) -> Result<User, ParseError> {
+    let user = User::from_json(json)?;
+    let Email(email) = user.email;
+    let email = match email.split('|').nth(1) {
+        Some(linked) => linked.trim_matches('>').to_owned(),
+        None => email,
+    };
+    Ok(User {
+        email: Email(email),
+        susp_ip: false,
+        ..user
+    })
+}
+
+/// Parses `none`, or actions joined with `+` like `shadowban+close`.
+fn parse_actions(s: &str) -> Result<Vec<Action>, ParseError> {
+    match s {
+        "none" => Ok(vec![]),
+        names => names.split('+').map(parse_action).collect(),
+    }
+}
+
+/// The longest duration `parse_duration` accepts, in minutes: about ten years.
+const MAX_DURATION_MINUTES: i64 = 10 * 366 * 24 * 60;
+
+/// Parses durations like `30m`, `12h`, `7d` or `2w`.
+fn parse_duration(s: &str) -> Result<Duration, ParseError> {
+    let (unit_start, unit) = match s.char_indices().last() {
+        Some(last) => last,
+        None => return Err(parse_error(Some("Invalid duration"))),
+    };
+    let minutes_per_unit = match unit {
+        'm' => 1,
+        'h' => 60,
+        'd' => 24 * 60,
+        'w' => 7 * 24 * 60,
+        _ => return Err(parse_error(Some("Durations end with `m`, `h`, `d` or `w`"))),
+    };
+    let amount: i64 = s[..unit_start].parse()?;
+    match amount.checked_mul(minutes_per_unit) {
+        Some(minutes) if minutes > 0 && minutes <= MAX_DURATION_MINUTES => {
+            Ok(Duration::minutes(minutes))
+        }
+        _ => Err(parse_error(Some(
+            "Durations must be positive and at most ten years",
+        ))),
+    }
+}
+
+/// The time `duration` from now, with a parse error if it is out of range.
+fn from_now(duration: Duration) -> Result<DateTime<Utc>, ParseError> {
+    Utc::now()
+        .checked_add_signed(duration)
+        .ok_or_else(|| parse_error(Some("Duration out of range")))
+}
+
+/// The time `duration` ago, with a parse error if it is out of range.
+fn ago(duration: Duration) -> Result<DateTime<Utc>, ParseError> {
+    Utc::now()
+        .checked_sub_signed(duration)
+        .ok_or_else(|| parse_error(Some("Duration out of range")))
+}
+
+/// Parses an action like `engine`, or one with a parameter like `warn:spam`.
+fn parse_action(token: &str) -> Result<Action, ParseError> {
+    let (key, param) = match token.find(':') {
+        Some(i) => (&token[..i], Some(&token[i + 1..])),
+        None => (token, None),
+    };
+    Ok(match (key, param) {
+        ("shadowban", None) => Action::Shadowban,
+        ("engine", None) => Action::EngineMark,
+        ("boost", None) => Action::BoostMark,
+        ("ipban", None) => Action::IpBan,
+        ("close", None) => Action::Close,
+        ("panic", None) => Action::EnableChatPanic,
+        ("notify", None) => Action::NotifySlack,
+        ("note", text) => Action::ModNote(text.map(|t| t.to_owned())),
+        ("warn", Some(preset)) => match WARNING_PRESETS.iter().find(|(k, _)| k.eq(&preset)) {
+            Some((_, subject)) => Action::Warn(subject.to_string()),
+            None => {
+                let presets: Vec<&str> = WARNING_PRESETS.iter().map(|(k, _)| *k).collect();
+                return Err(parse_error(Some(&format!(
+                    "Unknown warning `{}`, {}",
+                    preset,
+                    suggest(preset, &presets)
+                ))));
+            }
+        },
+        ("report", Some(reason)) => {
+            if !REPORT_REASONS.contains(&reason) {
+                return Err(parse_error(Some(&format!(
+                    "Unknown report reason `{}`, {}",
+                    reason,
+                    suggest(reason, REPORT_REASONS)
+                ))));
+            }
+            Action::Report(reason.to_owned())
+        }
+        ("alt", Some(main)) => Action::AltMark(main.to_owned()),
+        ("reportban", None) | ("reportban", Some("on")) => Action::ReportBan(true),
+        ("reportban", Some("off")) => Action::ReportBan(false),
+        ("resetrating", Some(perf)) => {
+            if !RATING_PERFS.contains(&perf) {
+                return Err(parse_error(Some(&format!(
+                    "Unknown rating `{}`, {}",
+                    perf,
+                    suggest(perf, RATING_PERFS)
+                ))));
+            }
+            Action::ResetRating(perf.to_owned())
+        }
+        _ if !ACTION_KEYS.contains(&key) => {
+            return Err(parse_error(Some(&format!(
+                "Unknown action `{}`, {}",
+                key,
+                suggest(key, ACTION_KEYS)
+            ))))
+        }
+        _ => {
+            return Err(parse_error(Some(&format!(
+                "Could not parse action `{}`",
+                token
+            ))))
+        }
+    })
+}
+
+/// Parses `none`, a fixed number of seconds like `10` or a random range like `30-180`.
+fn parse_delay(s: &str) -> Result<Delay, ParseError> {
+    if s == "none" {
+        return Ok(Delay::Immediate);
+    }
+    let bounds: Vec<&str> = s.split("-").collect();
+    match bounds.len() {
+        1 => Ok(Delay::Fixed(parse_millis(bounds[0])?)),
+        2 => {
+            let min = parse_millis(bounds[0])?;
+            let max = parse_millis(bounds[1])?;
+            if min > max {
+                return Err(parse_error(Some("Delay range minimum exceeds its maximum")));
+            }
+            Ok(Delay::Random(min, max))
+        }
+        _ => Err(parse_error(Some(
+            "Delays look like `none`, `10` or `30-180` (seconds)",
+        ))),
+    }
+}
+
+fn parse_millis(seconds: &str) -> Result<u64, ParseError> {
+    let seconds: f64 = seconds.parse()?;
+    if !seconds.is_finite() {
+        return Err(parse_error(Some("Delays are a number of seconds")));
+    }
+    if seconds < 0.0 {
+        return Err(parse_error(Some("Delays can't be negative")));
+    }
+    if seconds * 1000.0 > MAX_DELAY_MS as f64 {
+        return Err(parse_error(Some("Delays can't be longer than a day")));
+    }
+    Ok((seconds * 1000.0) as u64)
+}
+
+fn handle_external_command(command: &str) -> Result<Option<String>, ParseError> {
+    info!(command, "Running external command.");
+    // Not waited for: `./restart` ends this process, and waiting would block the runtime.
+    match std::process::Command::new(command).spawn() {
+        Ok(_) => Ok(None),
+        Err(_) => Ok(Some(String::from("Failed executing command."))),
+    }
+}
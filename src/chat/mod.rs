@@ -0,0 +1,309 @@
+pub mod command;
+mod parser;
+
+use crate::backoff::{Backoff, BackoffPolicy};
+use crate::chat::command::{handle_button, handle_command};
+use crate::discord::{DiscordWebhook, DiscordWebhookConfig};
+use crate::event::Event;
+use crate::health::Connection;
+use crate::metrics;
+use crate::slack::{Slack, SlackConfig};
+use crate::status::StatusPing;
+use crate::webhook::{Delivery, Webhook, WebhookConfig};
+use crate::zulip::{Zulip, ZulipConfig};
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
+
+/// Which chat platform the bot posts to and takes commands from.
+#[derive(Clone, Copy)]
+pub enum ChatConfig {
+    Slack(SlackConfig),
+    #[allow(dead_code)] // Only used if chosen in conf.rs.
+    Zulip(ZulipConfig),
+}
+
+/// A command to the bot, without the mention of the bot.
+pub struct Command {
+    /// The user ID (Slack) or email address (Zulip) of whoever sent the command.
+    pub author: String,
+    pub channel: String,
+    /// Where to reply: the Slack thread, or the Zulip topic.
+    pub thread: Option<String>,
+    pub text: String,
+}
+
+/// A chat platform. Channels are Slack channel IDs, or Zulip streams with an optional topic,
+/// as `stream>topic`.
+#[async_trait]
+pub trait Backend: Send + Sync {
+    async fn send_message(&self, text: String, channel: &str);
+
+    /// Posts a rule match, as plain text unless the backend can render it with buttons.
+    async fn send_match(&self, message: &MatchMessage, channel: &str) {
+        self.send_message(message.text.clone(), channel).await
+    }
+
+    /// Posts `content` as a file, or as a code block if the backend has no files.
+    async fn send_file(&self, content: String, filename: &str, channel: &str) {
+        self.send_message(format!("{}:\n```\n{}\n```", filename, content), channel)
+            .await
+    }
+
+    /// Replies to `command`, in its thread if it has one.
+    async fn reply(&self, text: String, command: &Command);
+
+    /// Connects, and passes the commands sent in `listener.channel` to it until the connection
+    /// closes.
+    async fn listen(
+        &self,
+        listener: &Listener,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+}
+
+/// The buttons on match messages, as `(action ID, label)`.
+pub const MATCH_BUTTONS: &[(&str, &str)] = &[
+    ("undo_actions", "Undo actions"),
+    ("disable_rule", "Disable rule"),
+    ("exempt_user", "Exempt user"),
+    ("false_positive", "Mark false positive"),
+];
+
+/// What a match message's buttons act on, as their JSON value.
+#[derive(Serialize, Deserialize)]
+pub struct ButtonValue {
+    pub rule: String,
+    pub username: String,
+}
+
+/// A rule match, as posted to the chat channel.
+pub struct MatchMessage {
+    pub rule: String,
+    pub username: String,
+    pub profile_url: String,
+    pub mod_url: String,
+    pub criterion: String,
+    pub actions: Vec<String>,
+    pub score: Option<String>,
+    pub previous_matches: usize,
+    /// Links to the latest caught users.
+    pub recent_matches: Vec<String>,
+    /// The whole message as text, for backends without rich messages.
+    pub text: String,
+}
+
+/// A button clicked on a match message.
+pub struct Button {
+    pub author: String,
+    pub action_id: String,
+    pub value: String,
+}
+
+/// A rule match, for notification sinks.
+#[derive(Serialize)]
+pub struct RuleMatch {
+    pub rule: String,
+    pub username: String,
+    pub profile_url: String,
+    pub criterion: String,
+    pub actions: Vec<String>,
+}
+
+/// A mod action that was sent, successfully or not.
+#[derive(Serialize)]
+pub struct ActionResult {
+    pub rule: String,
+    pub username: String,
+    pub action: String,
+    /// The HTTP status, or `error` if there was none.
+    pub status: String,
+    pub ok: bool,
+}
+
+/// An error evaluating a rule, usually in its Lua snippet.
+#[derive(Serialize)]
+pub struct RuleError {
+    pub rule: String,
+    pub username: String,
+    pub error: String,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Notification {
+    Match(RuleMatch),
+    Action(ActionResult),
+    Error(RuleError),
+    Alert { text: String },
+}
+
+impl Notification {
+    /// The kind, as in webhook payloads and configuration.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Notification::Match(_) => "match",
+            Notification::Action(_) => "action",
+            Notification::Error(_) => "error",
+            Notification::Alert { .. } => "alert",
+        }
+    }
+
+    /// The rule the notification is about, if any.
+    pub fn rule(&self) -> Option<&str> {
+        match self {
+            Notification::Match(m) => Some(&m.rule),
+            Notification::Action(a) => Some(&a.rule),
+            Notification::Error(e) => Some(&e.rule),
+            Notification::Alert { .. } => None,
+        }
+    }
+}
+
+/// Somewhere notifications are posted besides the chat, like a Discord webhook.
+#[async_trait]
+pub trait Sink: Send + Sync {
+    /// Whether `notification` is routed to this sink.
+    fn wants(&self, notification: &Notification) -> bool;
+
+    async fn notify(&self, notification: &Notification);
+}
+
+/// The configured chat backend and notification sinks.
+#[derive(Clone)]
+pub struct Chat {
+    backend: Arc<dyn Backend>,
+    sinks: Vec<Arc<dyn Sink>>,
+}
+
+impl Chat {
+    pub fn new(
+        config: ChatConfig,
+        discord_webhooks: &[DiscordWebhookConfig],
+        webhooks: &[WebhookConfig],
+        delivery: Delivery,
+    ) -> Result<Chat, Box<dyn std::error::Error>> {
+        let backend: Arc<dyn Backend> = match config {
+            ChatConfig::Slack(config) => Arc::new(Slack::new(config)),
+            ChatConfig::Zulip(config) => Arc::new(Zulip::new(config)),
+        };
+        let mut sinks: Vec<Arc<dyn Sink>> = vec![];
+        for webhook in discord_webhooks {
+            sinks.push(Arc::new(DiscordWebhook::new(*webhook)?));
+        }
+        for webhook in webhooks {
+            sinks.push(Arc::new(Webhook::new(*webhook, delivery)?));
+        }
+        Ok(Chat { backend, sinks })
+    }
+
+    /// Posts `notification` to the sinks it is routed to.
+    pub fn notify(&self, notification: Notification) {
+        let notification = Arc::new(notification);
+        for sink in self.sinks.iter().filter(|s| s.wants(&notification)) {
+            let sink = sink.clone();
+            let notification = notification.clone();
+            tokio::spawn(async move { sink.notify(&notification).await });
+        }
+    }
+
+    pub fn post_message(&self, text: String, channel: &'static str) {
+        let backend = self.backend.clone();
+        tokio::spawn(async move { backend.send_message(text, channel).await });
+    }
+
+    pub fn post_match(&self, message: MatchMessage, channel: &'static str) {
+        let backend = self.backend.clone();
+        tokio::spawn(async move { backend.send_match(&message, channel).await });
+    }
+
+    pub fn post_file(&self, content: String, filename: String, channel: &'static str) {
+        let backend = self.backend.clone();
+        tokio::spawn(async move { backend.send_file(content, &filename, channel).await });
+    }
+
+    /// Like `post_message`, but only returns once the message is sent.
+    pub async fn send_message(&self, text: String, channel: &'static str) {
+        self.backend.send_message(text, channel).await
+    }
+}
+
+/// What a backend needs to handle commands.
+pub struct Listener {
+    /// The channel to take commands from.
+    pub channel: &'static str,
+    tx: UnboundedSender<Event>,
+    status_tx: UnboundedSender<StatusPing>,
+}
+
+impl Listener {
+    pub fn connected(&self) -> Result<(), &'static str> {
+        self.status_tx
+            .send(StatusPing::Connected(Connection::Chat))
+            .map_err(|_| "Status loop stopped.")
+    }
+
+    /// Tells the status loop that the connection is alive.
+    pub fn ping(&self) -> Result<(), &'static str> {
+        self.status_tx
+            .send(StatusPing::ChatPingReceived)
+            .map_err(|_| "Status loop stopped.")
+    }
+
+    /// Handles `command`, and returns the reply, if any.
+    pub fn command(&self, command: &Command) -> Option<String> {
+        info!(author = %command.author, command = %command.text, "Command received.");
+        match handle_command(command.text.clone(), self.tx.clone()) {
+            Ok(s) => s,
+            Err(e) => Some(e.message),
+        }
+    }
+
+    /// Handles a click on a match message button, and returns the reply, if any.
+    pub fn button(&self, button: &Button) -> Option<String> {
+        info!(author = %button.author, action = %button.action_id, value = %button.value, "Button clicked.");
+        match handle_button(&button.action_id, &button.value, self.tx.clone()) {
+            Ok(s) => s,
+            Err(e) => Some(e.message),
+        }
+    }
+}
+
+pub fn connect(
+    chat: Chat,
+    listen_channel: &'static str,
+    tx: UnboundedSender<Event>,
+    status_tx: UnboundedSender<StatusPing>,
+    backoff_policy: BackoffPolicy,
+) -> JoinHandle<()> {
+    let listener = Listener {
+        channel: listen_channel,
+        tx,
+        status_tx: status_tx.clone(),
+    };
+
+    tokio::spawn(async move {
+        let mut backoff = Backoff::new(backoff_policy);
+        loop {
+            let started = Instant::now();
+            let error = match chat.backend.listen(&listener).await {
+                Ok(_) => "Chat connection closed.".to_owned(),
+                Err(err) => {
+                    error!(%err, "Err in chat connection.");
+                    err.to_string()
+                }
+            };
+            let _ = status_tx.send(StatusPing::Disconnected(Connection::Chat, error));
+
+            let delay = backoff.next_delay(started.elapsed());
+            info!(
+                "Reconnecting to chat in {:.1} seconds...",
+                delay.as_secs_f64()
+            );
+            sleep(delay).await;
+            metrics::RECONNECTS.with_label_values(&["chat"]).inc();
+        }
+    })
+}
@@ -0,0 +1,384 @@
+//! Splits chat commands into tokens, and reads them back with errors that point at a token.
+
+use std::error::Error;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TokenKind {
+    Word,
+    /// A word with "quoted" parts, which can contain spaces.
+    Quoted,
+    /// A `code span` or a ```code block```.
+    Code,
+}
+
+#[derive(Clone, Debug)]
+pub struct Token {
+    pub kind: TokenKind,
+    /// Without quotes and fences, and with Slack's escapes undone.
+    pub text: String,
+    /// Byte offsets in the command.
+    pub span: (usize, usize),
+}
+
+/// Splits `input` on whitespace, keeping quoted parts and code spans together.
+pub fn tokenize(input: &str) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = vec![];
+    let mut i = 0;
+    while let Some(c) = input[i..].chars().next() {
+        if c.is_whitespace() {
+            i += c.len_utf8();
+            continue;
+        }
+
+        if c == '`' {
+            let fence = if input[i..].starts_with("```") {
+                "```"
+            } else {
+                "`"
+            };
+            let body_start = i + fence.len();
+            let body_end = match input[body_start..].find(fence) {
+                Some(len) => body_start + len,
+                None => {
+                    let message = format!("Missing closing {}", fence);
+                    return Err(error_at(input, &tokens, (i, input.len()), &message));
+                }
+            };
+            let mut body = &input[body_start..body_end];
+            if fence == "```" && body.starts_with("json") {
+                body = &body[4..];
+            }
+            let end = body_end + fence.len();
+            tokens.push(Token {
+                kind: TokenKind::Code,
+                text: unescape(body.trim()),
+                span: (i, end),
+            });
+            i = end;
+            continue;
+        }
+
+        let start = i;
+        let mut kind = TokenKind::Word;
+        let mut text = String::new();
+        while let Some(c) = input[i..].chars().next() {
+            if c.is_whitespace() || c == '`' {
+                break;
+            }
+            let close = match c {
+                '"' => '"',
+                '“' => '”',
+                _ => {
+                    text.push(c);
+                    i += c.len_utf8();
+                    continue;
+                }
+            };
+            let body_start = i + c.len_utf8();
+            let body_end = match closing_quote(&input[body_start..], close) {
+                Some(len) => body_start + len,
+                None => {
+                    let message = format!("Missing closing {}", close);
+                    return Err(error_at(input, &tokens, (start, input.len()), &message));
+                }
+            };
+            text.push_str(
+                &input[body_start..body_end].replace(&format!("\\{}", close), &close.to_string()),
+            );
+            kind = TokenKind::Quoted;
+            i = body_end + close.len_utf8();
+        }
+        tokens.push(Token {
+            kind,
+            text: unescape(&text),
+            span: (start, i),
+        });
+    }
+    Ok(tokens)
+}
+
+/// The offset of the first `close` that is not escaped with a backslash.
+fn closing_quote(s: &str, close: char) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        if c == close && !escaped {
+            return Some(i);
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    None
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// Reads the tokens of a command in order.
+pub struct Parser<'a> {
+    input: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(input: &'a str) -> Result<Parser<'a>, ParseError> {
+        Ok(Parser {
+            input,
+            tokens: tokenize(input)?,
+            pos: 0,
+        })
+    }
+
+    pub fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    pub fn at_end(&self) -> bool {
+        self.pos >= self.tokens.len()
+    }
+
+    /// The next token, or an error saying that `what` is missing.
+    pub fn next(&mut self, what: &str) -> Result<Token, ParseError> {
+        match self.tokens.get(self.pos) {
+            Some(token) => {
+                self.pos += 1;
+                Ok(token.clone())
+            }
+            None => Err(self.error_at_next(&format!("Missing {}", what))),
+        }
+    }
+
+    /// The next token, read by `parse`.
+    pub fn parse_next<T, F>(&mut self, what: &str, parse: F) -> Result<T, ParseError>
+    where
+        F: FnOnce(&str) -> Result<T, ParseError>,
+    {
+        let token = self.next(what)?;
+        self.parse_token(&token, parse)
+    }
+
+    /// Reads `token` with `parse`, pointing its errors at the token.
+    pub fn parse_token<T, F>(&self, token: &Token, parse: F) -> Result<T, ParseError>
+    where
+        F: FnOnce(&str) -> Result<T, ParseError>,
+    {
+        parse(&token.text).map_err(|err| self.error_at(token, &err.message))
+    }
+
+    /// Takes the next token, which must be `keyword`.
+    pub fn keyword(&mut self, keyword: &str) -> Result<(), ParseError> {
+        match self.peek() {
+            Some(token) if token.text == keyword => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(token) => Err(self.error_at(token, &format!("Expected `{}`", keyword))),
+            None => Err(self.error_at_next(&format!("Missing `{}`", keyword))),
+        }
+    }
+
+    /// Takes the next token, which must be one of `options`.
+    pub fn one_of<'o>(&mut self, what: &str, options: &[&'o str]) -> Result<&'o str, ParseError> {
+        let token = self.next(what)?;
+        match options.iter().find(|o| **o == token.text) {
+            Some(option) => Ok(option),
+            None => Err(self.error_at(
+                &token,
+                &format!(
+                    "Unknown {} `{}`, {}",
+                    what,
+                    token.text,
+                    suggest(&token.text, options)
+                ),
+            )),
+        }
+    }
+
+    /// Takes the next token if it is the flag `name`.
+    pub fn flag(&mut self, name: &str) -> bool {
+        match self.peek() {
+            Some(token) if token.text == name => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Takes a keyword argument, written `name value` or `name=value`.
+    pub fn keyword_arg(&mut self, name: &str) -> Result<Option<Token>, ParseError> {
+        let token = match self.peek() {
+            Some(token) => token.clone(),
+            None => return Ok(None),
+        };
+        if token.text == name {
+            self.pos += 1;
+            return self.next(&format!("a value for `{}`", name)).map(Some);
+        }
+        match token
+            .text
+            .strip_prefix(name)
+            .and_then(|rest| rest.strip_prefix('='))
+        {
+            Some(value) => {
+                self.pos += 1;
+                Ok(Some(Token {
+                    kind: token.kind,
+                    text: value.to_owned(),
+                    span: token.span,
+                }))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Fails if any token is left.
+    pub fn end(&self) -> Result<(), ParseError> {
+        match self.peek() {
+            Some(token) => Err(self.error_at(token, &format!("Unexpected `{}`", token.text))),
+            None => Ok(()),
+        }
+    }
+
+    pub fn error_at(&self, token: &Token, message: &str) -> ParseError {
+        error_at(self.input, &self.tokens, token.span, message)
+    }
+
+    /// An error at the next token, or at the end of the command.
+    pub fn error_at_next(&self, message: &str) -> ParseError {
+        let end = self.input.len();
+        let span = self.peek().map(|t| t.span).unwrap_or((end, end));
+        error_at(self.input, &self.tokens, span, message)
+    }
+}
+
+/// "did you mean" the closest of `options`, or the list of them if none is close.
+pub fn suggest(got: &str, options: &[&str]) -> String {
+    let closest = options
+        .iter()
+        .map(|option| (distance(got, option), option))
+        .filter(|(d, _)| *d <= 2 && *d < got.chars().count())
+        .min_by_key(|(d, _)| *d);
+    match closest {
+        Some((_, option)) => format!("did you mean `{}`?", option),
+        None => format!("use one of: {}", options.join(", ")),
+    }
+}
+
+/// The Levenshtein distance between `a` and `b`.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + if ca == *cb { 0 } else { 1 };
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+fn error_at(input: &str, tokens: &[Token], span: (usize, usize), message: &str) -> ParseError {
+    ParseError {
+        message: format!("{}\n{}", message, point_at(input, tokens, span)),
+    }
+}
+
+/// The command on one line, in a code block, with carets under `span`.
+fn point_at(input: &str, tokens: &[Token], span: (usize, usize)) -> String {
+    let mut pieces: Vec<((usize, usize), String)> = tokens
+        .iter()
+        .map(|t| match &input[t.span.0..t.span.1] {
+            source if source.starts_with("```") => (t.span, "(code block)".to_owned()),
+            source => (t.span, shorten(source)),
+        })
+        .collect();
+    if !tokens.iter().any(|t| t.span == span) {
+        // The end of the command, or what could not be split into tokens.
+        pieces.push((span, shorten(&input[span.0..span.1])));
+    }
+
+    let mut line = String::new();
+    let mut carets = String::new();
+    for (piece_span, text) in pieces {
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        if piece_span == span {
+            carets = " ".repeat(line.chars().count()) + &"^".repeat(text.chars().count().max(1));
+        }
+        line.push_str(&text);
+    }
+    format!("```\n{}\n{}\n```", line, carets)
+}
+
+fn shorten(source: &str) -> String {
+    let one_line = source
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .replace("```", "'''");
+    if one_line.chars().count() > 40 {
+        one_line.chars().take(39).chain(Some('…')).collect()
+    } else {
+        one_line
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub message: String,
+}
+
+pub fn parse_error(msg: Option<&str>) -> ParseError {
+    ParseError {
+        message: msg.unwrap_or("Could not parse user command").to_owned(),
+    }
+}
+
+impl Error for ParseError {
+    fn description(&self) -> &str {
+        self.message.as_ref()
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl From<std::num::ParseIntError> for ParseError {
+    fn from(_: std::num::ParseIntError) -> Self {
+        parse_error(Some("Can't parse int"))
+    }
+}
+
+impl From<std::num::ParseFloatError> for ParseError {
+    fn from(_: std::num::ParseFloatError) -> Self {
+        parse_error(Some("Can't parse number"))
+    }
+}
+
+impl From<regex::Error> for ParseError {
+    fn from(err: regex::Error) -> Self {
+        parse_error(Some(format!("Invalid regex: {:?}", err).as_ref()))
+    }
+}
+
+impl From<rlua::Error> for ParseError {
+    fn from(_: rlua::Error) -> Self {
+        parse_error(Some("Invalid lua"))
+    }
+}
+
+impl From<serde_json::Error> for ParseError {
+    fn from(_: serde_json::Error) -> Self {
+        parse_error(Some("Can't (de)serialize"))
+    }
+}
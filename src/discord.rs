@@ -0,0 +1,111 @@
+use crate::chat::{Notification, Sink};
+use crate::http;
+use crate::metrics;
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::prelude::*;
+use http_body_util::Full;
+use hyper::header::CONTENT_TYPE;
+use hyper::{Request, Uri};
+use regex::Regex;
+
+/// Embed colors: red for matches, orange for alerts.
+const MATCH_COLOR: u32 = 0xd64f00;
+const ALERT_COLOR: u32 = 0xf0a000;
+
+#[derive(Clone, Copy)]
+pub struct DiscordWebhookConfig {
+    pub url: &'static str,
+    /// A regex of the names of the rules whose matches are posted, `None` for none.
+    pub rules: Option<&'static str>,
+    pub alerts: bool,
+}
+
+/// A Discord webhook, that rule matches and alerts are posted to as embeds.
+pub struct DiscordWebhook {
+    url: Uri,
+    rules: Option<Regex>,
+    alerts: bool,
+}
+
+impl DiscordWebhook {
+    pub fn new(config: DiscordWebhookConfig) -> Result<DiscordWebhook, Box<dyn std::error::Error>> {
+        let url: Uri = config.url.parse()?;
+        if url.scheme().is_none() || url.host().is_none() {
+            return Err(format!("Discord webhook URL `{}` is not absolute", config.url).into());
+        }
+        Ok(DiscordWebhook {
+            url,
+            rules: match config.rules {
+                Some(rules) => Some(Regex::new(rules)?),
+                None => None,
+            },
+            alerts: config.alerts,
+        })
+    }
+
+    fn embed(notification: &Notification) -> Option<serde_json::Value> {
+        match notification {
+            Notification::Match(m) => Some(json!({
+                "title": format!("Rule {} match: {}", m.rule, m.username),
+                "url": m.profile_url,
+                "color": MATCH_COLOR,
+                "fields": [
+                    {
+                        "name": "User",
+                        "value": format!("[{}]({})", m.username, m.profile_url),
+                        "inline": true
+                    },
+                    { "name": "Rule", "value": m.rule, "inline": true },
+                    { "name": "Criterion", "value": m.criterion },
+                    { "name": "Actions", "value": m.actions.join(", ") },
+                ],
+                "timestamp": Utc::now().to_rfc3339(),
+            })),
+            Notification::Alert { text } => Some(json!({
+                "description": text,
+                "color": ALERT_COLOR,
+                "timestamp": Utc::now().to_rfc3339(),
+            })),
+            _ => None,
+        }
+    }
+}
+
+#[async_trait]
+impl Sink for DiscordWebhook {
+    fn wants(&self, notification: &Notification) -> bool {
+        match notification {
+            Notification::Match(m) => self.rules.as_ref().is_some_and(|r| r.is_match(&m.rule)),
+            Notification::Alert { .. } => self.alerts,
+            _ => false,
+        }
+    }
+
+    async fn notify(&self, notification: &Notification) {
+        let embed = match DiscordWebhook::embed(notification) {
+            Some(embed) => embed,
+            None => return,
+        };
+        let content = json!({ "embeds": [embed] });
+        let req = Request::post(self.url.clone())
+            .header(CONTENT_TYPE, "application/json")
+            .body(Full::new(Bytes::from(content.to_string())))
+            .expect("the URL is checked by `new`");
+
+        match http::client().request(req).await {
+            Ok(res) if res.status().is_success() => {}
+            Ok(res) => {
+                metrics::SINK_FAILURES.with_label_values(&["discord"]).inc();
+                error!(
+                    status = res.status().as_u16(),
+                    "Error on posting to Discord webhook."
+                );
+            }
+            Err(err) => {
+                metrics::SINK_FAILURES.with_label_values(&["discord"]).inc();
+                error!(%err, "Error on posting to Discord webhook.");
+            }
+        }
+    }
+}
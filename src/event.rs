@@ -1,4 +1,5 @@
-use signup::rules::Rule;
+use crate::signup::rules::{Collision, Exemption, Rule, RuleEdit};
+use chrono::prelude::*;
 
 #[derive(Deserialize, Clone)]
 #[serde(tag = "t")]
@@ -11,11 +12,40 @@ pub enum Event {
     },
     InternalShowRule(String),
     InternalRemoveRule(String),
+    InternalEditRule {
+        name: String,
+        edit: RuleEdit,
+    },
     InternalDisableRules(String),
     InternalEnableRules(String),
     InternalListRules,
-    InternalStreamEventReceived,
+    InternalRuleStats,
+    /// Posts the rules whose names match the pattern, or all rules, as a JSON file.
+    InternalExportRules(Option<String>),
+    /// Imports rules, or previews the import unless `apply`.
+    InternalImportRules {
+        rules: Vec<Rule>,
+        on_collision: Collision,
+        apply: bool,
+    },
+    InternalAddExemption {
+        exemption: Exemption,
+    },
+    InternalRemoveExemption(String),
+    InternalListExemptions,
+    /// Cancels the pending actions on a user, and reverts the sent ones.
+    InternalRevertUser(String),
+    /// Cancels the pending actions of a rule, and reverts the ones it sent since `since`.
+    InternalRevertRule {
+        rule: String,
+        since: Option<DateTime<Utc>>,
+    },
+    InternalMarkFalsePositive {
+        rule: String,
+        username: String,
+    },
     InternalSlackStatusCommand,
+    InternalShutdown,
 }
 
 impl Event {
@@ -1,39 +1,83 @@
+use crate::chat::{Chat, MatchMessage, Notification, RuleError, RuleMatch};
+use crate::event::Event;
+use crate::http;
+use crate::lua;
+use crate::metrics;
+use crate::signup::audit::{AuditEntry, AuditLog};
+use crate::signup::pending::{PendingAction, PendingActions};
+use crate::signup::rules::*;
+use crate::status::StatusPing;
 use chrono::prelude::*;
-use event::Event;
-use futures::future;
-use hyper::header::HeaderValue;
-use hyper::rt::Future;
-use hyper::{Body, Client, Method, Request};
-use hyper_tls::HttpsConnector;
-use lua;
 use rand::{thread_rng, Rng};
-use signup::rules::*;
-use slack;
-use std::sync::mpsc::Receiver;
-use std::thread;
-use std::time;
-use tokio;
-
-pub fn handle_events(
-    rx: Receiver<Event>,
-    token: &'static str,
-    rules_path: &'static str,
-    slack_token: &'static str,
-    slack_channel: &'static str,
-    slack_notify_channel: &'static str,
-) {
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
+
+/// What `handle_events` needs from the configuration.
+pub struct HandlerConfig {
+    pub token: &'static str,
+    pub rules_path: &'static str,
+    pub exemptions_path: &'static str,
+    pub pending_actions_path: &'static str,
+    pub audit_path: &'static str,
+    pub delays: DelayDefaults,
+    pub endpoints: Endpoints,
+    pub precision_warning: PrecisionWarning,
+    pub chat_channel: &'static str,
+    pub notify_channel: &'static str,
+}
+
+/// Handles events until `Event::InternalShutdown`, then saves the rules and pending actions.
+/// Returns whether everything could be saved.
+pub async fn handle_events(
+    mut rx: UnboundedReceiver<Event>,
+    status_tx: UnboundedSender<StatusPing>,
+    chat: Chat,
+    config: HandlerConfig,
+) -> bool {
+    let HandlerConfig {
+        token,
+        rules_path,
+        exemptions_path,
+        pending_actions_path,
+        audit_path,
+        delays,
+        endpoints,
+        precision_warning,
+        chat_channel,
+        notify_channel,
+    } = config;
     let mut rule_manager =
-        SignupRulesManager::new(rules_path.to_string()).expect("could not load rules");
-    println!("Currently {} rules.", rule_manager.rules.len());
+        SignupRulesManager::new(rules_path.to_string(), exemptions_path.to_string())
+            .expect("could not load rules");
+    info!("Currently {} rules.", rule_manager.rules.len());
 
-    let mut latest_event_utc: DateTime<Utc> = Utc::now();
+    endpoints
+        .validate()
+        .expect("invalid action endpoint configuration");
 
     let lua_state = lua::new_lua();
 
     let mut recently_notified: Vec<String> = vec![];
 
-    loop {
-        let event = rx.recv().unwrap();
+    let mut recent_signups = RecentSignups::new();
+
+    let audit = Arc::new(AuditLog::new(audit_path.to_string()));
+    let mut pending_actions = PendingActions::new(
+        pending_actions_path.to_string(),
+        audit.clone(),
+        token,
+        http::client(),
+        status_tx.clone(),
+        chat.clone(),
+    )
+    .expect("could not load pending actions");
+
+    send_rule_counts(&rule_manager, &status_tx);
+
+    while let Some(event) = rx.recv().await {
         let event2 = event.clone();
 
         match event {
@@ -44,94 +88,169 @@ pub fn handle_events(
                     _ => panic!("This is impossible."),
                 };
 
-                let delay_ms_if_needed = thread_rng().gen_range(30, 180) * 1000;
+                if !hypothetical && !recent_signups.insert(&user.username.0) {
+                    warn!(username = %user.username.0, "Dropped duplicate signup event.");
+                    metrics::DUPLICATE_EVENTS.inc();
+                    continue;
+                }
+
+                let delay_sample: f64 = thread_rng().gen();
 
                 let mut matched_rules: Vec<String> = vec![];
 
-                for rule in &rule_manager.rules {
-                    let take_action = if !rule.enabled {
-                        Ok(false)
-                    } else if rule.susp_ip && !user.susp_ip {
+                let exemption = rule_manager.matching_exemption(&user, &lua_state);
+                let mut exempted_rules: Vec<String> = vec![];
+
+                let mut score_breakdown: Vec<(String, i32)> = vec![];
+
+                // Score rules go last, so that all weights are in by the time they are checked.
+                // They don't add to the score themselves, so they all see the same one.
+                let ordered_rules = rule_manager
+                    .rules
+                    .iter()
+                    .filter(|r| !r.criterion.is_score())
+                    .chain(rule_manager.rules.iter().filter(|r| r.criterion.is_score()));
+
+                for rule in ordered_rules {
+                    let score = if rule.criterion.is_score() {
+                        Some(score_breakdown.iter().map(|(_, weight)| weight).sum())
+                    } else {
+                        None
+                    };
+
+                    let take_action = if !rule.enabled || (rule.susp_ip && !user.susp_ip) {
                         Ok(false)
                     } else {
-                        rule.criterion.take_action(&user, &lua_state)
+                        metrics::RULE_EVALUATIONS
+                            .with_label_values(&[&rule.name])
+                            .inc();
+                        let started = Instant::now();
+                        let result = rule.criterion.take_action(&user, score, &lua_state);
+                        metrics::RULE_EVALUATION_SECONDS
+                            .with_label_values(&[&rule.name])
+                            .observe(started.elapsed().as_secs_f64());
+                        match result {
+                            Ok(true) if !rule.is_signal() => {
+                                metrics::RULE_MATCHES.with_label_values(&[&rule.name]).inc()
+                            }
+                            Ok(_) => {}
+                            Err(_) => metrics::LUA_ERRORS.with_label_values(&[&rule.name]).inc(),
+                        }
+                        result
                     };
 
+                    if rule.weight != 0
+                        && !rule.criterion.is_score()
+                        && take_action.clone().unwrap_or(false)
+                    {
+                        score_breakdown.push((rule.name.clone(), rule.weight));
+                        // Signal rules are only seen in the score of the score rules they trigger.
+                        if rule.is_signal() {
+                            continue;
+                        }
+                    }
+
+                    if exemption.is_some() && take_action.clone().unwrap_or(false) {
+                        exempted_rules.push(rule.name.clone());
+                        continue;
+                    }
+
                     if hypothetical && take_action.clone().unwrap_or(false) {
-                        slack::web::post_message(
+                        chat.post_message(
                             format!(
-                                "Rule {} would take these actions: {:?}",
-                                &rule.name, &rule.actions
+                                "Rule {} would take these actions: {:?}{}",
+                                &rule.name,
+                                &rule.actions,
+                                if rule.criterion.is_score() {
+                                    format!(". Score: {}", friendly_score(&score_breakdown))
+                                } else if rule.weight != 0 {
+                                    format!(". Weight: {:+}", rule.weight)
+                                } else {
+                                    "".to_owned()
+                                }
                             ),
-                            slack_token,
-                            slack_channel,
+                            chat_channel,
                         );
                     }
-                    let take_real_action = if take_action.is_ok() {
-                        Ok(take_action.unwrap() && !hypothetical)
-                    } else {
-                        take_action
-                    };
+                    let take_real_action = take_action.map(|take| take && !hypothetical);
 
                     match take_real_action {
                         Ok(true) => {
+                            info!(
+                                rule = %rule.name,
+                                username = %user.username.0,
+                                actions = ?rule.actions,
+                                "Rule matched."
+                            );
                             matched_rules.push(rule.name.clone());
-
-                            let bearer = "Bearer ".to_owned() + token;
+                            chat.notify(Notification::Match(RuleMatch {
+                                rule: rule.name.clone(),
+                                username: user.username.0.clone(),
+                                profile_url: endpoints.profile_url(&user.username.0),
+                                criterion: rule.criterion.friendly(),
+                                actions: rule.actions.iter().map(|a| a.key().to_owned()).collect(),
+                            }));
 
                             for action in &rule.actions {
-                                match action.api_endpoint(&user.username) {
+                                match action.api_endpoint(&user.username, &endpoints) {
                                     Some(endpoint) => {
-                                        let mut action_req = Request::new(Body::from(""));
-                                        *action_req.uri_mut() = endpoint.parse().unwrap();
-                                        *action_req.method_mut() = Method::POST;
-                                        action_req.headers_mut().insert(
-                                            hyper::header::AUTHORIZATION,
-                                            HeaderValue::from_str(&bearer).unwrap(),
-                                        );
-
-                                        let https = HttpsConnector::new(1).unwrap();
-                                        let client = Client::builder().build::<_, Body>(https);
-
-                                        let delay = !rule.no_delay
-                                            && (action.eq(&Action::EngineMark)
-                                                || action.eq(&Action::BoostMark)
-                                                || action.eq(&Action::IpBan)
-                                                || action.eq(&Action::Close));
-
-                                        let delay_additional =
-                                            if !rule.no_delay && action.eq(&Action::Close) {
-                                                1500
-                                            } else {
-                                                0
-                                            };
-
-                                        tokio::spawn(future::lazy(move || {
-                                            if delay {
-                                                thread::sleep(time::Duration::from_millis(
-                                                    delay_ms_if_needed + delay_additional,
-                                                ));
+                                        let delay_ms =
+                                            rule.delay_for(action, &delays).millis(delay_sample);
+
+                                        let due = chrono::Duration::from_std(
+                                            std::time::Duration::from_millis(delay_ms),
+                                        )
+                                        .ok()
+                                        .and_then(|delay| Utc::now().checked_add_signed(delay));
+                                        let due = match due {
+                                            Some(due) => due,
+                                            None => {
+                                                error!(
+                                                    rule = %rule.name,
+                                                    action = action.key(),
+                                                    delay_ms,
+                                                    "Delay out of range."
+                                                );
+                                                chat.post_message(
+                                                    format!(
+                                                        "Error on `{}` for user `{}`: the delay of \
+                                                         {} is out of range, not sending it.",
+                                                        &rule.name,
+                                                        &user.username.0,
+                                                        action.key()
+                                                    ),
+                                                    chat_channel,
+                                                );
+                                                continue;
                                             }
+                                        };
 
-                                            client
-                                                .request(action_req)
-                                                .map(|res| println!("Action: {}.", res.status()))
-                                                .map_err(|err| {
-                                                    println!("Error on mod action: {}", err);
-                                                })
-                                        }));
+                                        metrics::ACTION_DELAY_SECONDS
+                                            .with_label_values(&[action.key()])
+                                            .observe(delay_ms as f64 / 1000.0);
+
+                                        pending_actions.schedule(PendingAction {
+                                            rule: rule.name.clone(),
+                                            username: user.username.0.clone(),
+                                            action: action.key().to_owned(),
+                                            endpoint,
+                                            body: action.api_body(&user.username, rule),
+                                            due,
+                                            revert: action
+                                                .api_revert_endpoint(&user.username, &endpoints),
+                                        });
                                     }
                                     None => {
                                         if action.eq(&Action::NotifySlack)
                                             && !recently_notified.contains(&user.username.0)
                                         {
-                                            slack::web::post_message(
+                                            chat.post_message(
                                                 format!(
-                                                    "Rule {} match: https://lichess.org/@/{}",
-                                                    &rule.name, &user.username.0
+                                                    "Rule {} match: {}",
+                                                    &rule.name,
+                                                    endpoints.public_profile_url(&user.username.0)
                                                 ),
-                                                slack_token,
-                                                slack_notify_channel,
+                                                notify_channel,
                                             );
 
                                             recently_notified.insert(0, user.username.0.clone());
@@ -144,36 +263,55 @@ pub fn handle_events(
                             }
 
                             if rule.actions.len() > 1
-                                || !rule.actions.get(0).eq(&Some(&Action::NotifySlack))
+                                || rule.actions.first() != Some(&Action::NotifySlack)
                             {
-                                slack::web::post_message(
-                                    format!(
-                                        "Rule {} match: \
-                                         {} on <https://lichess.org/@/{}?mod|{}>. \
-                                         {} previous matches. \
-                                         Recent matches: {}",
-                                        &rule.name,
-                                        &rule.criterion.friendly(),
-                                        &user.username.0,
-                                        &user.username.0,
-                                        &rule.match_count,
-                                        if rule.most_recent_caught.len() == 0 {
-                                            "None".to_string()
-                                        } else {
-                                            rule.most_recent_caught
-                                                .iter()
-                                                .map(|u| {
-                                                    format!(
-                                                        "<https://lichess.org/@/{}?mod|{}>",
-                                                        &u, &u
-                                                    )
-                                                })
-                                                .collect::<Vec<String>>()
-                                                .join(", ")
-                                        }
-                                    ),
-                                    slack_token,
-                                    slack_channel,
+                                let score = if rule.criterion.is_score() {
+                                    Some(friendly_score(&score_breakdown))
+                                } else {
+                                    None
+                                };
+                                let recent_matches: Vec<String> = rule
+                                    .most_recent_caught
+                                    .iter()
+                                    .map(|u| endpoints.profile_link(u))
+                                    .collect();
+                                let text = format!(
+                                    "Rule {} match: \
+                                     {} on {}.{} \
+                                     {} previous matches. \
+                                     Recent matches: {}",
+                                    &rule.name,
+                                    &rule.criterion.friendly(),
+                                    endpoints.profile_link(&user.username.0),
+                                    match &score {
+                                        Some(score) => format!(" Score: {}.", score),
+                                        None => "".to_owned(),
+                                    },
+                                    &rule.match_count,
+                                    if recent_matches.is_empty() {
+                                        "None".to_string()
+                                    } else {
+                                        recent_matches.join(", ")
+                                    }
+                                );
+                                chat.post_match(
+                                    MatchMessage {
+                                        rule: rule.name.clone(),
+                                        username: user.username.0.clone(),
+                                        profile_url: endpoints.public_profile_url(&user.username.0),
+                                        mod_url: endpoints.profile_url(&user.username.0),
+                                        criterion: rule.criterion.friendly(),
+                                        actions: rule
+                                            .actions
+                                            .iter()
+                                            .map(|a| a.key().to_owned())
+                                            .collect(),
+                                        score,
+                                        previous_matches: rule.match_count,
+                                        recent_matches,
+                                        text,
+                                    },
+                                    chat_channel,
                                 );
                             }
                         }
@@ -183,45 +321,104 @@ pub fn handle_events(
                                 "Error on `{}` for user `{}` (probably in Lua snippet): `{}`",
                                 &rule.name, &user.username.0, err
                             );
-                            println!("{}", err_msg.clone());
-                            slack::web::post_message(err_msg, slack_token, slack_channel);
+                            warn!(
+                                rule = %rule.name,
+                                username = %user.username.0,
+                                %err,
+                                "Error evaluating rule."
+                            );
+                            let _ = status_tx.send(StatusPing::LuaError {
+                                rule: rule.name.clone(),
+                                username: user.username.0.clone(),
+                                error: err.to_string(),
+                            });
+                            chat.notify(Notification::Error(RuleError {
+                                rule: rule.name.clone(),
+                                username: user.username.0.clone(),
+                                error: err.to_string(),
+                            }));
+                            chat.post_message(err_msg, chat_channel);
+                        }
+                    }
+                }
+
+                if let Some(exemption) = exemption {
+                    if !exempted_rules.is_empty() {
+                        chat.post_message(
+                            format!(
+                                "Exemption {} {} actions of rules {} on {}.",
+                                &exemption,
+                                if hypothetical {
+                                    "would prevent"
+                                } else {
+                                    "prevented"
+                                },
+                                exempted_rules.join(", "),
+                                endpoints.profile_link(&user.username.0)
+                            ),
+                            chat_channel,
+                        );
+
+                        if !hypothetical {
+                            match rule_manager.exempted(exemption) {
+                                Ok(_) => {}
+                                Err(e) => error!(%e, "Error in .exempted."),
+                            };
                         }
                     }
                 }
 
                 if !hypothetical {
                     for name in matched_rules {
+                        if let Err(err) = audit.record_match(&name, &user.username.0) {
+                            error!(%err, "Error on writing to the audit trail.");
+                        }
                         match rule_manager.caught(name, &user.username) {
                             Ok(_) => {}
-                            Err(e) => println!("Error in .caught: {}", e),
+                            Err(e) => error!(%e, "Error in .caught."),
                         };
                     }
                 }
             }
             Event::InternalAddRule { rule } => match rule_manager.add_rule(rule) {
                 Err(err) => {
-                    println!("Error on .add_rule: {}", err);
-                    slack::web::post_message(
-                        format!("Error on adding rule: {}", err),
-                        slack_token,
-                        slack_channel,
-                    );
+                    error!(%err, "Error on .add_rule.");
+                    chat.post_message(format!("Error on adding rule: {}", err), chat_channel);
                 }
                 Ok(_) => {
-                    slack::web::post_message("Rule added!".to_owned(), slack_token, slack_channel);
+                    chat.post_message("Rule added!".to_owned(), chat_channel);
                 }
             },
             Event::InternalShowRule(name) => {
                 let slack_message = match rule_manager.find_rule(name) {
                     None => "No such rule found.".to_owned(),
-                    Some(rule) => format!(
-                        "Criterion: {}.\nActions: {:?}{}",
-                        rule.criterion.friendly(),
-                        rule.actions,
-                        if rule.no_delay { ". No delay." } else { "" }
-                    ),
+                    Some(rule) => rule.friendly(&delays, &endpoints),
                 };
-                slack::web::post_message(slack_message, slack_token, slack_channel);
+                chat.post_message(slack_message, chat_channel);
+            }
+            Event::InternalEditRule { name, edit } => {
+                let new_name = match &edit {
+                    RuleEdit::Rename(new_name) if new_name != &name => Some(new_name.clone()),
+                    _ => None,
+                };
+                let slack_message = match rule_manager.edit_rule(&name, edit) {
+                    Ok(Some(change)) => {
+                        info!(rule = %name, %change, "Rule edited.");
+                        if let Some(new_name) = new_name {
+                            pending_actions.rename_rule(&name, &new_name);
+                            if let Err(err) = audit.record_rename(&name, &new_name) {
+                                error!(%err, "Error on writing to the audit trail.");
+                            }
+                        }
+                        format!("Rule {} edited: {}.", name, change)
+                    }
+                    Ok(None) => "No such rule found.".to_owned(),
+                    Err(err) => {
+                        error!(%err, "Error on .edit_rule.");
+                        format!("Error on editing rule: {}", err)
+                    }
+                };
+                chat.post_message(slack_message, chat_channel);
             }
             Event::InternalRemoveRule(name) => {
                 let slack_message = match rule_manager.remove_rule(name) {
@@ -233,40 +430,356 @@ pub fn handle_events(
                         }
                     }
                     Err(err) => {
-                        println!("Error on .remove_rule: {}", err);
+                        error!(%err, "Error on .remove_rule.");
                         format!("Error on removing rule: {}", err)
                     }
                 };
-                slack::web::post_message(slack_message, slack_token, slack_channel);
+                chat.post_message(slack_message, chat_channel);
             }
             Event::InternalDisableRules(pattern) => {
                 let slack_message = match rule_manager.disable_rules(pattern) {
                     Ok(count) => format!("{} rules disabled.", count),
                     Err(err) => format!("Error on disabling rules: {}", err),
                 };
-                slack::web::post_message(slack_message, slack_token, slack_channel);
+                chat.post_message(slack_message, chat_channel);
             }
             Event::InternalEnableRules(pattern) => {
                 let slack_message = match rule_manager.enable_rules(pattern) {
                     Ok(count) => format!("{} rules enabled.", count),
                     Err(err) => format!("Error on enabling rules: {}", err),
                 };
-                slack::web::post_message(slack_message, slack_token, slack_channel);
+                chat.post_message(slack_message, chat_channel);
             }
-            Event::InternalListRules => slack::web::post_message(
+            Event::InternalListRules => chat.post_message(
                 format!("Current rules: {}", rule_manager.list_names().join(", ")),
-                slack_token,
-                slack_channel,
+                chat_channel,
             ),
-            Event::InternalStreamEventReceived => latest_event_utc = Utc::now(),
-            Event::InternalSlackStatusCommand => slack::web::post_message(
-                format!(
-                    "I am alive! Latest event: (UTC) {}",
-                    latest_event_utc.format("%d/%m/%Y %T")
+            Event::InternalAddExemption { exemption } => {
+                let slack_message = match rule_manager.add_exemption(exemption) {
+                    Ok(_) => "Exemption added!".to_owned(),
+                    Err(err) => {
+                        error!(%err, "Error on .add_exemption.");
+                        format!("Error on adding exemption: {}", err)
+                    }
+                };
+                chat.post_message(slack_message, chat_channel);
+            }
+            Event::InternalRemoveExemption(name) => {
+                let slack_message = match rule_manager.remove_exemption(name) {
+                    Ok(removed) => {
+                        if removed {
+                            "Exemption removed!".to_owned()
+                        } else {
+                            "No such exemption found.".to_owned()
+                        }
+                    }
+                    Err(err) => {
+                        error!(%err, "Error on .remove_exemption.");
+                        format!("Error on removing exemption: {}", err)
+                    }
+                };
+                chat.post_message(slack_message, chat_channel);
+            }
+            Event::InternalListExemptions => chat.post_message(
+                if rule_manager.exemptions.is_empty() {
+                    "No exemptions.".to_owned()
+                } else {
+                    format!(
+                        "Current exemptions:\n{}",
+                        rule_manager.list_exemptions().join("\n")
+                    )
+                },
+                chat_channel,
+            ),
+            Event::InternalRevertUser(username) => {
+                let cancelled =
+                    pending_actions.cancel(|a| a.username.eq_ignore_ascii_case(&username));
+                let sent = pending_actions.revertible(
+                    |e| e.username.eq_ignore_ascii_case(&username),
+                    |a| a.username.eq_ignore_ascii_case(&username),
+                );
+                revert(
+                    &mut pending_actions,
+                    cancelled,
+                    sent,
+                    endpoints.profile_link(&username),
+                    &chat,
+                    chat_channel,
+                );
+            }
+            Event::InternalRevertRule { rule, since } => {
+                let cancelled = pending_actions.cancel(|a| a.rule == rule);
+                let sent = pending_actions.revertible(
+                    |e| e.rule == rule && since.is_none_or(|since| e.at >= since),
+                    |a| a.rule == rule,
+                );
+                revert(
+                    &mut pending_actions,
+                    cancelled,
+                    sent,
+                    format!("users caught by rule {}", rule),
+                    &chat,
+                    chat_channel,
+                );
+            }
+            Event::InternalMarkFalsePositive { rule, username } => {
+                let caught = audit.caught(&rule, &username).unwrap_or_else(|err| {
+                    error!(%err, "Error on reading the audit trail.");
+                    false
+                });
+                let slack_message = match rule_manager.mark_false_positive(&rule, &username, caught)
+                {
+                    Ok(None) => "No such rule found.".to_owned(),
+                    Ok(Some((_, FalsePositive::AlreadyMarked))) => format!(
+                        "{} is already marked as a false positive of rule {}.",
+                        endpoints.profile_link(&username),
+                        rule
+                    ),
+                    Ok(Some((_, FalsePositive::NotCaught))) => format!(
+                        "{} was not caught by rule {}, not marking it as a false positive.",
+                        endpoints.profile_link(&username),
+                        rule
+                    ),
+                    Ok(Some((marked, FalsePositive::Marked))) => {
+                        info!(%rule, %username, "Match marked as false positive.");
+                        metrics::FALSE_POSITIVES.with_label_values(&[&rule]).inc();
+                        format!(
+                            "Marked the match of {} by rule {} as a false positive. \
+                             Precision: {}.{}",
+                            endpoints.profile_link(&username),
+                            rule,
+                            friendly_percent(marked.precision()),
+                            if marked.low_precision(&precision_warning) {
+                                format!(
+                                    " This is below {}, consider changing or disabling the rule.",
+                                    friendly_percent(Some(precision_warning.below))
+                                )
+                            } else {
+                                "".to_owned()
+                            }
+                        )
+                    }
+                    Err(err) => {
+                        error!(%err, "Error on .mark_false_positive.");
+                        format!("Error on marking false positive: {}", err)
+                    }
+                };
+                chat.post_message(slack_message, chat_channel);
+            }
+            Event::InternalExportRules(pattern) => match rule_manager.export(pattern.as_deref()) {
+                Ok(rules) if rules.is_empty() => {
+                    chat.post_message("No rules to export.".to_owned(), chat_channel)
+                }
+                Ok(rules) => match serde_json::to_string_pretty(&rules) {
+                    Ok(json) => chat.post_file(
+                        json,
+                        format!("rules-{}.json", Utc::now().format("%Y%m%d-%H%M%S")),
+                        chat_channel,
+                    ),
+                    Err(err) => chat
+                        .post_message(format!("Error on exporting rules: {}", err), chat_channel),
+                },
+                Err(err) => chat.post_message(
+                    format!("Error on exporting rules: invalid regex: {}", err),
+                    chat_channel,
                 ),
-                slack_token,
-                slack_channel,
+            },
+            Event::InternalImportRules {
+                rules,
+                on_collision,
+                apply,
+            } => {
+                let slack_message = match rule_manager.import(rules, on_collision, apply) {
+                    Ok(report) if apply => format!("Rules imported. {}", report.friendly()),
+                    Ok(report) => format!(
+                        "Import preview. {} \
+                         Run the command again with `--apply` to import, and with `--replace` \
+                         or `--rename` to import rules whose name is taken.",
+                        report.friendly()
+                    ),
+                    Err(err) => {
+                        error!(%err, "Error on .import.");
+                        format!("Error on importing rules: {}", err)
+                    }
+                };
+                chat.post_message(slack_message, chat_channel);
+            }
+            Event::InternalRuleStats => chat.post_message(
+                rule_stats(&rule_manager.rules, &precision_warning),
+                chat_channel,
             ),
+            Event::InternalSlackStatusCommand => {
+                let (report_tx, report_rx) = oneshot::channel();
+                // Gone with the status loop, then `report_rx` is closed right away.
+                let _ = status_tx.send(StatusPing::Report(report_tx));
+                let slack_message = match report_rx.await {
+                    Ok(report) => report.friendly(),
+                    Err(_) => "I am alive! The status is unavailable.".to_owned(),
+                };
+                chat.post_message(slack_message, chat_channel);
+            }
+            Event::InternalShutdown => break,
+        }
+
+        send_rule_counts(&rule_manager, &status_tx);
+    }
+
+    rx.close();
+
+    let mut clean = true;
+    if let Err(err) = rule_manager.flush() {
+        error!(%err, "Error on saving rules.");
+        clean = false;
+    }
+    let slack_message = match pending_actions.shutdown().await {
+        Ok(saved) => format!("Shutting down, {} pending actions saved.", saved),
+        Err(err) => {
+            error!(%err, "Error on saving pending actions.");
+            clean = false;
+            format!("Shutting down, could not save pending actions: {}", err)
+        }
+    };
+    info!("{}", slack_message);
+    chat.send_message(slack_message, chat_channel).await;
+
+    clean
+}
+
+/// A table of the statistics of all rules, flagging those with a low precision.
+fn rule_stats(rules: &[Rule], warning: &PrecisionWarning) -> String {
+    if rules.is_empty() {
+        return "No rules.".to_owned();
+    }
+    let width = rules.iter().map(|r| r.name.len()).max().unwrap_or(0).max(4);
+    let mut lines = vec![format!(
+        "{:width$}  {:>7}  {:>9}  {:>9}  {:>7}  {}",
+        "rule",
+        "matches",
+        "false pos",
+        "precision",
+        "per day",
+        "last match",
+        width = width
+    )];
+    for rule in rules {
+        lines.push(format!(
+            "{:width$}  {:>7}  {:>9}  {:>9}  {:>7}  {}{}",
+            rule.name,
+            rule.match_count,
+            rule.false_positives.len(),
+            friendly_percent(rule.precision()),
+            rule.matches_per_day()
+                .map_or("-".to_owned(), |d| format!("{:.1}", d)),
+            friendly_time(rule.last_match),
+            if rule.low_precision(warning) {
+                "  !"
+            } else {
+                ""
+            },
+            width = width
+        ));
+    }
+    let mut table = format!("```\n{}\n```", lines.join("\n"));
+    if rules.iter().any(|r| r.low_precision(warning)) {
+        table.push_str(&format!(
+            "\n! Precision below {}.",
+            friendly_percent(Some(warning.below))
+        ));
+    }
+    table
+}
+
+/// Reports the `cancelled` pending actions on `what`, and reverts the `sent` ones. Actions still
+/// being sent are only reported.
+fn revert(
+    pending_actions: &mut PendingActions,
+    cancelled: usize,
+    sent: Result<(Vec<AuditEntry>, usize), Box<dyn std::error::Error>>,
+    what: String,
+    chat: &Chat,
+    chat_channel: &'static str,
+) {
+    let (sent, sending) = match sent {
+        Ok(sent) => sent,
+        Err(err) => {
+            error!(%err, "Error on reading the audit trail.");
+            chat.post_message(
+                format!(
+                    "Cancelled {} pending actions on {}, but could not read the audit trail: {}",
+                    cancelled, what, err
+                ),
+                chat_channel,
+            );
+            return;
+        }
+    };
+    info!(%what, cancelled, reverting = sent.len(), sending, "Reverting actions.");
+    let not_reverted = if sending > 0 {
+        format!(
+            " {} actions were being sent and were not reverted, revert again once they are.",
+            sending
+        )
+    } else {
+        "".to_owned()
+    };
+    if cancelled == 0 && sent.is_empty() {
+        chat.post_message(
+            format!("Nothing to revert on {}.{}", what, not_reverted),
+            chat_channel,
+        );
+        return;
+    }
+    chat.post_message(
+        format!(
+            "Cancelled {} pending actions and reverting {} sent actions on {}.{}",
+            cancelled,
+            sent.len(),
+            what,
+            not_reverted
+        ),
+        chat_channel,
+    );
+    if !sent.is_empty() {
+        pending_actions.revert(sent, what, chat_channel);
+    }
+}
+
+fn send_rule_counts(rule_manager: &SignupRulesManager, status_tx: &UnboundedSender<StatusPing>) {
+    let _ = status_tx.send(StatusPing::Rules {
+        total: rule_manager.rules.len(),
+        enabled: rule_manager.rules.iter().filter(|r| r.enabled).count(),
+    });
+}
+
+const RECENT_SIGNUPS: usize = 1000;
+
+/// The latest signed up usernames, so that a signup delivered twice is only acted on once.
+struct RecentSignups {
+    order: VecDeque<String>,
+    seen: HashSet<String>,
+}
+
+impl RecentSignups {
+    fn new() -> RecentSignups {
+        RecentSignups {
+            order: VecDeque::new(),
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Returns false if the username was already seen.
+    fn insert(&mut self, username: &str) -> bool {
+        let id = username.to_lowercase();
+        if self.seen.contains(&id) {
+            return false;
+        }
+        self.seen.insert(id.clone());
+        self.order.push_back(id);
+        if self.order.len() > RECENT_SIGNUPS {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
         }
+        true
     }
 }
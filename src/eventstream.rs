@@ -1,77 +1,124 @@
-use chrono::offset::Utc;
-use chrono::DateTime;
-use event::Event;
-use futures::future;
-use futures::future::Loop;
-use hyper::header::HeaderValue;
-use hyper::rt::{Future, Stream};
-use hyper::{Body, Client, Request};
-use hyper_tls::HttpsConnector;
-use status::StatusPing;
-use std::sync::mpsc::Sender;
-use std::thread;
-use std::time::SystemTime;
+use crate::backoff::{Backoff, BackoffPolicy};
+use crate::event::Event;
+use crate::health::Connection;
+use crate::http;
+use crate::metrics;
+use crate::status::StatusPing;
+use bytes::{Bytes, BytesMut};
+use http_body_util::{BodyExt, Full};
+use hyper::header::AUTHORIZATION;
+use hyper::Request;
+use std::time::Instant;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
 
-pub fn watch_event_stream(tx: Sender<Event>, token: &'static str, status_tx: Sender<StatusPing>) {
-    tokio::spawn(future::loop_fn((), move |_| {
-        let https = HttpsConnector::new(2).unwrap();
-        let client = Client::builder().build::<_, Body>(https);
+pub fn watch_event_stream(
+    tx: UnboundedSender<Event>,
+    lichess_url: &'static str,
+    token: &'static str,
+    status_tx: UnboundedSender<StatusPing>,
+    backoff_policy: BackoffPolicy,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut backoff = Backoff::new(backoff_policy);
+        loop {
+            let started = Instant::now();
+            let error = match read_event_stream(&tx, lichess_url, token, &status_tx).await {
+                Ok(_) => "Event stream ended.".to_owned(),
+                Err(err) => {
+                    error!(%err, "Error on get.");
+                    err.to_string()
+                }
+            };
+            let _ = status_tx.send(StatusPing::Disconnected(Connection::Lichess, error));
 
-        let mut req = Request::new(Body::from(""));
-        *req.uri_mut() = "https://lichess.org/api/stream/mod".parse().unwrap();
+            let delay = backoff.next_delay(started.elapsed());
+            info!(
+                "Reconnecting to Lichess event stream in {:.1} seconds...",
+                delay.as_secs_f64()
+            );
+            sleep(delay).await;
+            metrics::RECONNECTS.with_label_values(&["lichess"]).inc();
+        }
+    })
+}
+
+async fn read_event_stream(
+    tx: &UnboundedSender<Event>,
+    lichess_url: &'static str,
+    token: &'static str,
+    status_tx: &UnboundedSender<StatusPing>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let req = Request::get(format!(
+        "{}/api/stream/mod",
+        lichess_url.trim_end_matches('/')
+    ))
+    .header(AUTHORIZATION, "Bearer ".to_owned() + token)
+    .body(Full::new(Bytes::new()))?;
 
-        let bearer = "Bearer ".to_owned() + token;
+    let res = http::client().request(req).await?;
+    if !res.status().is_success() {
+        return Err(format!("Event stream responded with {}.", res.status()).into());
+    }
+    info!("Event stream connection initialized.");
+    status_tx
+        .send(StatusPing::Connected(Connection::Lichess))
+        .map_err(|_| "Status loop stopped.")?;
 
-        req.headers_mut().insert(
-            hyper::header::AUTHORIZATION,
-            HeaderValue::from_str(&bearer).unwrap(),
-        );
+    let mut body = res.into_body();
+    let mut count = 0;
+    // Events can be split across chunks: what follows the last newline waits for the next one.
+    let mut buffer = BytesMut::new();
 
-        let tx2 = tx.clone();
-        let status_tx2 = status_tx.clone();
+    while let Some(frame) = body.frame().await {
+        let chunk = match frame?.into_data() {
+            Ok(chunk) => chunk,
+            Err(_) => continue,
+        };
 
-        let mut count = 0;
+        status_tx
+            .send(StatusPing::StreamEventReceived)
+            .map_err(|_| "Status loop stopped.")?;
 
-        client
-            .request(req)
-            .and_then(move |res| {
-                println!("Event stream connection initialized.");
-                res.into_body().for_each(move |chunk| {
-                    status_tx2.send(StatusPing::StreamEventReceived).unwrap();
-                    tx2.send(Event::InternalStreamEventReceived).unwrap();
+        buffer.extend_from_slice(&chunk);
+        let complete = match buffer.iter().rposition(|b| *b == b'\n') {
+            Some(newline) => buffer.split_to(newline + 1),
+            None => continue,
+        };
+        let string_chunk =
+            &String::from_utf8(complete.to_vec()).unwrap_or("invalid chunk bytes".to_string());
+        let lines: Vec<&str> = string_chunk.split("\n").collect();
+        for line in &lines {
+            count += 1;
+            if count % 20 == 0 {
+                debug!("20 done");
+                count = 0;
+            }
 
-                    let string_chunk = &String::from_utf8(chunk.into_bytes().to_vec())
-                        .unwrap_or("invalid chunk bytes".to_string());
-                    let lines: Vec<&str> = string_chunk.split("\n").collect();
-                    for line in &lines {
-                        count = count + 1;
-                        if count % 20 == 0 {
-                            let now = SystemTime::now();
-                            let dt: DateTime<Utc> = now.into();
-                            println!("UTC {}: 20 done", dt.format("%d/%m/%Y %T"));
-                            count = 0;
-                        }
+            let trimmed = line.trim();
+            if !trimmed.is_empty() {
+                let event_type = serde_json::from_str::<serde_json::Value>(trimmed)
+                    .ok()
+                    .and_then(|v| v["t"].as_str().map(|t| t.to_owned()))
+                    .unwrap_or("unknown".to_owned());
+                status_tx
+                    .send(StatusPing::EventReceived(event_type.clone()))
+                    .map_err(|_| "Status loop stopped.")?;
+                metrics::EVENTS_RECEIVED
+                    .with_label_values(&[&event_type])
+                    .inc();
 
-                        let trimmed = line.trim();
-                        if !trimmed.eq("") {
-                            match Event::from_json(line) {
-                                Ok(event) => tx2.send(event).unwrap(),
-                                _ => {
-                                    println!("deserialize error for {}", line);
-                                }
-                            };
-                        }
+                match Event::from_json(line) {
+                    Ok(event) => tx.send(event).map_err(|_| "Event handler stopped.")?,
+                    _ => {
+                        metrics::DESERIALIZE_ERRORS.inc();
+                        warn!(event_type = %event_type, line = %trimmed, "Deserialize error.");
                     }
-                    Ok(())
-                })
-            })
-            .map_err(|err| {
-                println!("Error on get: {}", err);
-            })
-            .and_then(|_| {
-                println!("Reconnecting to Lichess event stream in 7 seconds...");
-                thread::sleep(std::time::Duration::from_millis(7000));
-                Ok(Loop::Continue(()))
-            })
-    }));
+                };
+            }
+        }
+    }
+
+    Ok(())
 }
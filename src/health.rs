@@ -0,0 +1,325 @@
+use chrono::prelude::*;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How long the reconnects, action results and Lua errors are kept for alerts.
+const HISTORY: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Connection {
+    Lichess,
+    Chat,
+}
+
+#[derive(Serialize, Clone)]
+pub struct LastError {
+    pub at: DateTime<Utc>,
+    pub message: String,
+}
+
+#[derive(Serialize, Clone)]
+pub struct ConnectionHealth {
+    pub connected: bool,
+    pub connected_since: Option<DateTime<Utc>>,
+    pub reconnects: u64,
+    pub last_error: Option<LastError>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct LuaError {
+    pub at: DateTime<Utc>,
+    pub rule: String,
+    pub username: String,
+    pub error: String,
+}
+
+/// Events per minute, averaged over the last 1, 5 and 60 minutes.
+#[derive(Serialize, Clone)]
+pub struct EventRates {
+    pub last_1m: f64,
+    pub last_5m: f64,
+    pub last_60m: f64,
+}
+
+#[derive(Serialize, Clone)]
+pub struct HealthReport {
+    pub uptime_secs: u64,
+    pub lichess: ConnectionHealth,
+    pub chat: ConnectionHealth,
+    pub latest_event: Option<DateTime<Utc>>,
+    pub events_per_minute: EventRates,
+    pub pending_actions: usize,
+    pub rules: usize,
+    pub enabled_rules: usize,
+    pub last_lua_error: Option<LuaError>,
+}
+
+/// The state behind `HealthReport`, kept up to date by `status::status_loop`.
+pub struct Health {
+    started: Instant,
+    lichess: ConnectionHealth,
+    chat: ConnectionHealth,
+    latest_event: Option<DateTime<Utc>>,
+    /// Event counts by minute since `started`, for the last hour.
+    event_counts: VecDeque<(u64, u64)>,
+    pending_actions: usize,
+    rules: usize,
+    enabled_rules: usize,
+    last_lua_error: Option<LuaError>,
+    last_signup: Instant,
+    /// The last data on the event stream, keep-alive newlines included.
+    last_stream_data: Instant,
+    reconnect_times: VecDeque<(Instant, Connection)>,
+    action_results: VecDeque<(Instant, bool)>,
+    lua_error_times: VecDeque<Instant>,
+}
+
+fn prune<T>(history: &mut VecDeque<T>, at: impl Fn(&T) -> Instant) {
+    while history.front().is_some_and(|t| at(t).elapsed() > HISTORY) {
+        history.pop_front();
+    }
+}
+
+impl Health {
+    pub fn new() -> Health {
+        let disconnected = ConnectionHealth {
+            connected: false,
+            connected_since: None,
+            reconnects: 0,
+            last_error: None,
+        };
+        Health {
+            started: Instant::now(),
+            lichess: disconnected.clone(),
+            chat: disconnected,
+            latest_event: None,
+            event_counts: VecDeque::new(),
+            pending_actions: 0,
+            rules: 0,
+            enabled_rules: 0,
+            last_lua_error: None,
+            last_signup: Instant::now(),
+            last_stream_data: Instant::now(),
+            reconnect_times: VecDeque::new(),
+            action_results: VecDeque::new(),
+            lua_error_times: VecDeque::new(),
+        }
+    }
+
+    fn connection(&mut self, connection: Connection) -> &mut ConnectionHealth {
+        match connection {
+            Connection::Lichess => &mut self.lichess,
+            Connection::Chat => &mut self.chat,
+        }
+    }
+
+    pub fn connected(&mut self, connection: Connection) {
+        let c = self.connection(connection);
+        c.connected = true;
+        c.connected_since = Some(Utc::now());
+    }
+
+    pub fn disconnected(&mut self, connection: Connection, error: String) {
+        let c = self.connection(connection);
+        c.connected = false;
+        c.connected_since = None;
+        c.reconnects += 1;
+        c.last_error = Some(LastError {
+            at: Utc::now(),
+            message: error,
+        });
+
+        self.reconnect_times.push_back((Instant::now(), connection));
+        prune(&mut self.reconnect_times, |(t, _)| *t);
+    }
+
+    fn minute(&self) -> u64 {
+        self.started.elapsed().as_secs() / 60
+    }
+
+    pub fn stream_data_received(&mut self) {
+        self.last_stream_data = Instant::now();
+    }
+
+    pub fn event_received(&mut self, event_type: &str) {
+        self.latest_event = Some(Utc::now());
+        if event_type == "signup" {
+            self.last_signup = Instant::now();
+        }
+
+        let minute = self.minute();
+        match self.event_counts.back_mut() {
+            Some((m, count)) if *m == minute => *count += 1,
+            _ => self.event_counts.push_back((minute, 1)),
+        }
+        while self
+            .event_counts
+            .front()
+            .is_some_and(|(m, _)| m + 60 <= minute)
+        {
+            self.event_counts.pop_front();
+        }
+    }
+
+    fn rate(&self, minutes: u64) -> f64 {
+        let now = self.minute();
+        let count: u64 = self
+            .event_counts
+            .iter()
+            .filter(|(m, _)| m + minutes > now)
+            .map(|(_, count)| count)
+            .sum();
+        count as f64 / minutes as f64
+    }
+
+    pub fn pending_actions(&mut self, count: usize) {
+        self.pending_actions = count;
+    }
+
+    pub fn rules(&mut self, rules: usize, enabled_rules: usize) {
+        self.rules = rules;
+        self.enabled_rules = enabled_rules;
+    }
+
+    pub fn action_sent(&mut self, ok: bool) {
+        self.action_results.push_back((Instant::now(), ok));
+        prune(&mut self.action_results, |(t, _)| *t);
+    }
+
+    pub fn lua_error(&mut self, rule: String, username: String, error: String) {
+        self.lua_error_times.push_back(Instant::now());
+        prune(&mut self.lua_error_times, |t| *t);
+        self.last_lua_error = Some(LuaError {
+            at: Utc::now(),
+            rule,
+            username,
+            error,
+        });
+    }
+
+    /// Time since the last signup, or since the start if there was none.
+    pub fn since_last_signup(&self) -> Duration {
+        self.last_signup.elapsed()
+    }
+
+    /// Time since the last data on the event stream, or since the start if there was none.
+    pub fn since_stream_data(&self) -> Duration {
+        self.last_stream_data.elapsed()
+    }
+
+    pub fn reconnects_within(&self, connection: Connection, window: Duration) -> usize {
+        self.reconnect_times
+            .iter()
+            .filter(|(t, c)| *c == connection && t.elapsed() <= window)
+            .count()
+    }
+
+    /// Successful and failed actions within `window`.
+    pub fn action_results_within(&self, window: Duration) -> (usize, usize) {
+        let recent = self
+            .action_results
+            .iter()
+            .filter(|(t, _)| t.elapsed() <= window);
+        let ok = recent.clone().filter(|(_, ok)| *ok).count();
+        (ok, recent.count() - ok)
+    }
+
+    pub fn lua_errors_within(&self, window: Duration) -> usize {
+        self.lua_error_times
+            .iter()
+            .filter(|t| t.elapsed() <= window)
+            .count()
+    }
+
+    pub fn report(&self) -> HealthReport {
+        HealthReport {
+            uptime_secs: self.started.elapsed().as_secs(),
+            lichess: self.lichess.clone(),
+            chat: self.chat.clone(),
+            latest_event: self.latest_event,
+            events_per_minute: EventRates {
+                last_1m: self.rate(1),
+                last_5m: self.rate(5),
+                last_60m: self.rate(60),
+            },
+            pending_actions: self.pending_actions,
+            rules: self.rules,
+            enabled_rules: self.enabled_rules,
+            last_lua_error: self.last_lua_error.clone(),
+        }
+    }
+}
+
+fn friendly_time(t: &DateTime<Utc>) -> String {
+    format!("(UTC) {}", t.format("%d/%m/%Y %T"))
+}
+
+fn friendly_duration(secs: u64) -> String {
+    if secs >= 86400 {
+        format!("{}d {}h", secs / 86400, secs % 86400 / 3600)
+    } else if secs >= 3600 {
+        format!("{}h {}m", secs / 3600, secs % 3600 / 60)
+    } else {
+        format!("{}m {}s", secs / 60, secs % 60)
+    }
+}
+
+impl ConnectionHealth {
+    fn friendly(&self) -> String {
+        let state = match self.connected_since {
+            Some(since) if self.connected => format!(
+                "connected for {}",
+                friendly_duration((Utc::now() - since).num_seconds().max(0) as u64)
+            ),
+            _ => "reconnecting".to_owned(),
+        };
+        format!(
+            "{}, {} reconnects{}",
+            state,
+            self.reconnects,
+            match self.last_error {
+                Some(ref e) => format!(", last error {}: `{}`", friendly_time(&e.at), e.message),
+                None => "".to_owned(),
+            }
+        )
+    }
+}
+
+impl HealthReport {
+    pub fn healthy(&self) -> bool {
+        self.lichess.connected && self.chat.connected
+    }
+
+    pub fn friendly(&self) -> String {
+        format!(
+            "I am alive! Uptime: {}.\n\
+             Lichess event stream: {}.\n\
+             Chat: {}.\n\
+             Latest event: {}. Events per minute: {:.1} (1m), {:.1} (5m), {:.1} (60m).\n\
+             Pending actions: {}. Rules: {} ({} enabled).{}",
+            friendly_duration(self.uptime_secs),
+            self.lichess.friendly(),
+            self.chat.friendly(),
+            match self.latest_event {
+                Some(ref t) => friendly_time(t),
+                None => "none".to_owned(),
+            },
+            self.events_per_minute.last_1m,
+            self.events_per_minute.last_5m,
+            self.events_per_minute.last_60m,
+            self.pending_actions,
+            self.rules,
+            self.enabled_rules,
+            match self.last_lua_error {
+                Some(ref e) => format!(
+                    "\nLast Lua error {}: `{}` on `{}`: `{}`",
+                    friendly_time(&e.at),
+                    e.rule,
+                    e.username,
+                    e.error
+                ),
+                None => "".to_owned(),
+            }
+        )
+    }
+}
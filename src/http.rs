@@ -0,0 +1,13 @@
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper_tls::HttpsConnector;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+
+pub type HttpsClient = Client<HttpsConnector<HttpConnector>, Full<Bytes>>;
+
+/// A client for `https://` as well as plain `http://` URLs, which dev instances and mocks use.
+pub fn client() -> HttpsClient {
+    Client::builder(TokioExecutor::new()).build(HttpsConnector::new())
+}
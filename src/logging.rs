@@ -0,0 +1,52 @@
+use std::sync::Mutex;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, EnvFilter, Registry};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Text,
+    /// One JSON object per line, with the event fields (`rule`, `username`, ...) as keys.
+    Json,
+}
+
+lazy_static! {
+    static ref FILTER: Mutex<Option<reload::Handle<EnvFilter, Registry>>> = Mutex::new(None);
+}
+
+/// Installs the global logger. `level` is an `EnvFilter` directive, like `info` or
+/// `info,lichess_event_stream::eventstream=debug`.
+pub fn init(level: &str, format: LogFormat) -> Result<(), Box<dyn std::error::Error>> {
+    let (filter, handle) = reload::Layer::new(EnvFilter::try_new(level)?);
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(if format == LogFormat::Json {
+            Some(fmt::layer().json().flatten_event(true))
+        } else {
+            None
+        })
+        .with(if format == LogFormat::Text {
+            Some(fmt::layer())
+        } else {
+            None
+        })
+        .try_init()?;
+    *FILTER.lock().unwrap() = Some(handle);
+    Ok(())
+}
+
+/// Changes the level directives of the running logger.
+pub fn set_level(level: &str) -> Result<(), String> {
+    let filter = EnvFilter::try_new(level).map_err(|e| format!("Invalid log level: {}", e))?;
+    match *FILTER.lock().unwrap() {
+        Some(ref handle) => handle.reload(filter).map_err(|e| e.to_string()),
+        None => Err("Logging is not initialized.".to_owned()),
+    }
+}
+
+pub fn level() -> Option<String> {
+    match *FILTER.lock().unwrap() {
+        Some(ref handle) => handle.with_current(|filter| filter.to_string()).ok(),
+        None => None,
+    }
+}
@@ -1,6 +1,5 @@
-use event::User;
+use crate::event::User;
 use regex::Regex;
-use rlua;
 use rlua::{Function, Lua, UserData, UserDataMethods};
 use std::net::IpAddr;
 
@@ -32,18 +31,17 @@ pub fn new_lua() -> Lua {
             .unwrap();
         let is_in_ip_range = lua_ctx
             .create_function(|_, (ip, min, max): (String, String, String)| {
-                let addr = ip.parse();
-                let min_addr = min.parse();
-                let max_addr = max.parse();
-                if addr.is_err() || min_addr.is_err() || max_addr.is_err() {
-                    Err(rlua::Error::RuntimeError(String::from(
+                match (
+                    ip.parse::<IpAddr>(),
+                    min.parse::<IpAddr>(),
+                    max.parse::<IpAddr>(),
+                ) {
+                    (Ok(addr), Ok(min_addr), Ok(max_addr)) => {
+                        Ok(addr >= min_addr && addr <= max_addr)
+                    }
+                    _ => Err(rlua::Error::RuntimeError(String::from(
                         "Invalid IP in one of the arguments",
-                    )))
-                } else {
-                    let addr: IpAddr = addr.unwrap();
-                    let min_addr: IpAddr = min_addr.unwrap();
-                    let max_addr: IpAddr = max_addr.unwrap();
-                    Ok(addr >= min_addr && addr <= max_addr)
+                    ))),
                 }
             })
             .unwrap();
@@ -1,64 +1,111 @@
-#![feature(try_trait)]
-
-extern crate chrono;
-extern crate futures;
-extern crate hyper;
-extern crate hyper_tls;
-extern crate rand;
-extern crate tokio;
-extern crate tungstenite;
-extern crate url;
-
+#[macro_use]
+extern crate lazy_static;
+#[macro_use]
+extern crate prometheus;
 #[macro_use]
 extern crate serde_derive;
-
-extern crate serde;
 #[macro_use]
 extern crate serde_json;
-extern crate regex;
-extern crate serde_regex;
-
-extern crate rlua;
+#[macro_use]
+extern crate tracing;
 
+mod admin;
+mod alerts;
+mod backoff;
+mod chat;
 mod conf;
+mod discord;
 mod event;
 mod eventhandler;
 mod eventstream;
+mod health;
+mod http;
+mod logging;
 mod lua;
+mod metrics;
 mod signup;
 mod slack;
 mod status;
+#[cfg(test)]
+mod tests;
+mod webhook;
+mod zulip;
+
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc::unbounded_channel;
 
-use futures::future;
-use std::sync::mpsc::channel;
+#[tokio::main]
+async fn main() {
+    logging::init(conf::LOG_LEVEL, conf::LOG_FORMAT).expect("could not initialize logging");
 
-fn main() {
-    tokio::run(future::lazy(move || {
-        let (tx, rx) = channel::<event::Event>();
-        let (status_tx, status_rx) = channel::<status::StatusPing>();
+    let (tx, rx) = unbounded_channel::<event::Event>();
+    let (status_tx, status_rx) = unbounded_channel::<status::StatusPing>();
 
-        eventstream::watch_event_stream(tx.clone(), conf::TOKEN, status_tx.clone());
+    match admin::serve(conf::ADMIN_ADDR, status_tx.clone()).await {
+        Ok(addr) => info!("Serving metrics and health on http://{}.", addr),
+        Err(err) => error!(%err, "Could not serve metrics and health on {}.", conf::ADMIN_ADDR),
+    }
 
-        slack::rtm::connect_to_slack(
-            conf::SLACK_BOT_TOKEN,
-            conf::SLACK_BOT_USER_ID,
-            conf::SLACK_CHANNEL,
-            tx.clone(),
-            status_tx.clone(),
-        );
+    let chat = chat::Chat::new(
+        conf::CHAT,
+        conf::DISCORD_WEBHOOKS,
+        conf::WEBHOOKS,
+        conf::WEBHOOK_DELIVERY,
+    )
+    .expect("invalid webhook configuration");
 
-        status::status_loop(status_rx, tx.clone(), conf::TOKEN, status_tx.clone());
-        status::periodically_ensure_alive_connection(status_tx.clone());
+    status::status_loop(
+        status_rx,
+        tx.clone(),
+        status_tx.clone(),
+        chat.clone(),
+        status::StatusConfig {
+            lichess_url: conf::ENDPOINTS.base_url,
+            token: conf::TOKEN,
+            chat_channel: conf::CHAT_CHANNEL,
+            notify_channel: conf::CHAT_NOTIFY_CHANNEL,
+            alerts: conf::ALERTS,
+            backoff: conf::RECONNECT_BACKOFF,
+            stream_timeout: conf::STREAM_TIMEOUT,
+        },
+    );
+    status::periodically_ensure_alive_connection(status_tx.clone(), Duration::from_secs(15));
 
-        eventhandler::handle_events(
-            rx,
-            conf::TOKEN,
-            conf::RULES_PATH,
-            conf::SLACK_BOT_TOKEN,
-            conf::SLACK_CHANNEL,
-            conf::SLACK_NOTIFY_CHANNEL,
-        );
+    let shutdown_tx = tx.clone();
+    tokio::spawn(async move {
+        wait_for_shutdown_signal().await;
+        info!("Shutdown signal received.");
+        let _ = shutdown_tx.send(event::Event::InternalShutdown);
+    });
+
+    let clean = eventhandler::handle_events(
+        rx,
+        status_tx.clone(),
+        chat,
+        eventhandler::HandlerConfig {
+            token: conf::TOKEN,
+            rules_path: conf::RULES_PATH,
+            exemptions_path: conf::EXEMPTIONS_PATH,
+            pending_actions_path: conf::PENDING_ACTIONS_PATH,
+            audit_path: conf::AUDIT_PATH,
+            delays: conf::DELAYS,
+            endpoints: conf::ENDPOINTS,
+            precision_warning: conf::PRECISION_WARNING,
+            chat_channel: conf::CHAT_CHANNEL,
+            notify_channel: conf::CHAT_NOTIFY_CHANNEL,
+        },
+    )
+    .await;
+    if !clean {
+        std::process::exit(1);
+    }
+}
 
-        Ok(())
-    }));
+async fn wait_for_shutdown_signal() {
+    let mut sigterm = signal(SignalKind::terminate()).expect("could not listen for SIGTERM");
+    tokio::select! {
+        _ = sigterm.recv() => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
 }
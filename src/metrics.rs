@@ -0,0 +1,101 @@
+use prometheus::{Encoder, HistogramVec, IntCounter, IntCounterVec, TextEncoder};
+
+lazy_static! {
+    pub static ref EVENTS_RECEIVED: IntCounterVec = register_int_counter_vec!(
+        "lichess_events_received_total",
+        "Events received on the lichess event stream, by type.",
+        &["type"]
+    )
+    .unwrap();
+    pub static ref DESERIALIZE_ERRORS: IntCounter = register_int_counter!(
+        "lichess_event_deserialize_errors_total",
+        "Event stream lines that could not be deserialized."
+    )
+    .unwrap();
+    pub static ref DUPLICATE_EVENTS: IntCounter = register_int_counter!(
+        "lichess_duplicate_events_total",
+        "Signup events dropped because the same signup was already handled."
+    )
+    .unwrap();
+    pub static ref RULE_EVALUATIONS: IntCounterVec = register_int_counter_vec!(
+        "signup_rule_evaluations_total",
+        "Signup rule evaluations, by rule.",
+        &["rule"]
+    )
+    .unwrap();
+    pub static ref FALSE_POSITIVES: IntCounterVec = register_int_counter_vec!(
+        "signup_rule_false_positives_total",
+        "Matches marked as false positives by moderators, by rule.",
+        &["rule"]
+    )
+    .unwrap();
+    pub static ref RULE_MATCHES: IntCounterVec = register_int_counter_vec!(
+        "signup_rule_matches_total",
+        "Signups matched, by rule. Includes hypothetical and exempted signups.",
+        &["rule"]
+    )
+    .unwrap();
+    pub static ref LUA_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "signup_rule_lua_errors_total",
+        "Errors evaluating a rule criterion, by rule.",
+        &["rule"]
+    )
+    .unwrap();
+    pub static ref ACTIONS_SENT: IntCounterVec = register_int_counter_vec!(
+        "mod_actions_sent_total",
+        "Mod actions sent to lichess, by action and HTTP status (`error` if there was none).",
+        &["action", "status"]
+    )
+    .unwrap();
+    pub static ref ACTIONS_REVERTED: IntCounterVec = register_int_counter_vec!(
+        "mod_actions_reverted_total",
+        "Mod actions reverted, by action and HTTP status (`error` if there was none).",
+        &["action", "status"]
+    )
+    .unwrap();
+    pub static ref CHAT_POST_FAILURES: IntCounter = register_int_counter!(
+        "chat_post_failures_total",
+        "Chat messages and files that could not be posted."
+    )
+    .unwrap();
+    pub static ref SINK_FAILURES: IntCounterVec = register_int_counter_vec!(
+        "notification_sink_failures_total",
+        "Notifications that could not be posted to a sink, by sink (`discord`).",
+        &["sink"]
+    )
+    .unwrap();
+    pub static ref WEBHOOK_DEAD_LETTERS: IntCounter = register_int_counter!(
+        "webhook_dead_letters_total",
+        "Webhook payloads that were not delivered after all attempts."
+    )
+    .unwrap();
+    pub static ref RECONNECTS: IntCounterVec = register_int_counter_vec!(
+        "reconnects_total",
+        "Reconnections, by connection (`lichess` or `chat`).",
+        &["connection"]
+    )
+    .unwrap();
+    pub static ref RULE_EVALUATION_SECONDS: HistogramVec = register_histogram_vec!(
+        "signup_rule_evaluation_seconds",
+        "Time taken to evaluate a rule criterion, by rule.",
+        &["rule"],
+        vec![0.000_01, 0.000_1, 0.001, 0.01, 0.1, 1.0]
+    )
+    .unwrap();
+    pub static ref ACTION_DELAY_SECONDS: HistogramVec = register_histogram_vec!(
+        "mod_action_delay_seconds",
+        "Delay before sending a mod action, by action.",
+        &["action"],
+        vec![0.0, 1.0, 10.0, 30.0, 60.0, 120.0, 180.0, 300.0]
+    )
+    .unwrap();
+}
+
+/// All metrics, in the Prometheus text format.
+pub fn render() -> String {
+    let mut buffer = vec![];
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buffer)
+        .unwrap();
+    String::from_utf8(buffer).unwrap()
+}
@@ -0,0 +1,209 @@
+use chrono::prelude::*;
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::sync::Mutex;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEvent {
+    /// A rule caught the user. The action and status are empty.
+    Matched,
+    Sent,
+    /// Cancelled while waiting for its delay.
+    Cancelled,
+    Reverted,
+    /// The rule was renamed to `renamed_to`. The earlier entries of the rule are its entries.
+    Renamed,
+}
+
+/// A line of the audit trail.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AuditEntry {
+    pub at: DateTime<Utc>,
+    pub event: AuditEvent,
+    pub rule: String,
+    pub username: String,
+    pub action: String,
+    /// The HTTP status, `error` if there was none, or empty for cancelled actions.
+    pub status: String,
+    pub ok: bool,
+    /// The endpoint that undoes a sent action, if it can be undone.
+    #[serde(default)]
+    pub revert: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub renamed_to: Option<String>,
+}
+
+/// What happened to mod actions, as JSON lines appended to `path`.
+pub struct AuditLog {
+    path: String,
+    /// Read from `path` on first use and kept up to date by `record`.
+    index: Mutex<Option<Index>>,
+}
+
+#[derive(Default)]
+struct Index {
+    /// The sent actions that can still be reverted.
+    revertible: Vec<AuditEntry>,
+    /// The rules and lowercase usernames of the matches, and of the sent actions.
+    caught: HashSet<(String, String)>,
+}
+
+impl Index {
+    fn add(&mut self, entry: &AuditEntry) {
+        match entry.event {
+            AuditEvent::Matched | AuditEvent::Sent => {
+                self.caught
+                    .insert((entry.rule.clone(), entry.username.to_lowercase()));
+            }
+            _ => {}
+        }
+        match entry.event {
+            AuditEvent::Sent if entry.ok && entry.revert.is_some() => {
+                self.revertible.push(entry.clone())
+            }
+            AuditEvent::Reverted if entry.ok => self.revertible.retain(|sent| {
+                sent.rule != entry.rule
+                    || sent.action != entry.action
+                    || !sent.username.eq_ignore_ascii_case(&entry.username)
+            }),
+            AuditEvent::Renamed => {
+                let new_name = entry.renamed_to.clone().unwrap_or_default();
+                for sent in &mut self.revertible {
+                    if sent.rule == entry.rule {
+                        sent.rule = new_name.clone();
+                    }
+                }
+                self.caught = self
+                    .caught
+                    .drain()
+                    .map(|(rule, username)| {
+                        if rule == entry.rule {
+                            (new_name.clone(), username)
+                        } else {
+                            (rule, username)
+                        }
+                    })
+                    .collect();
+            }
+            _ => {}
+        }
+    }
+}
+
+impl AuditLog {
+    pub fn new(path: String) -> AuditLog {
+        AuditLog {
+            path,
+            index: Mutex::new(None),
+        }
+    }
+
+    pub fn record(&self, entry: &AuditEntry) -> Result<(), Box<dyn std::error::Error>> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+
+        let mut index = self.index.lock().unwrap();
+        OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&self.path)?
+            .write_all(line.as_bytes())?;
+        if let Some(ref mut index) = *index {
+            index.add(entry);
+        }
+        Ok(())
+    }
+
+    /// Records that the rule `rule` caught `username`.
+    pub fn record_match(
+        &self,
+        rule: &str,
+        username: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.record(&AuditEntry {
+            at: Utc::now(),
+            event: AuditEvent::Matched,
+            rule: rule.to_owned(),
+            username: username.to_owned(),
+            action: String::new(),
+            status: String::new(),
+            ok: true,
+            revert: None,
+            renamed_to: None,
+        })
+    }
+
+    /// Records that the rule `rule` is now called `new_name`.
+    pub fn record_rename(
+        &self,
+        rule: &str,
+        new_name: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.record(&AuditEntry {
+            at: Utc::now(),
+            event: AuditEvent::Renamed,
+            rule: rule.to_owned(),
+            username: String::new(),
+            action: String::new(),
+            status: String::new(),
+            ok: true,
+            revert: None,
+            renamed_to: Some(new_name.to_owned()),
+        })
+    }
+
+    /// Runs `f` on the index, reading the audit trail first if it wasn't yet.
+    fn with_index<T, F: FnOnce(&Index) -> T>(&self, f: F) -> Result<T, Box<dyn std::error::Error>> {
+        let mut index = self.index.lock().unwrap();
+        if index.is_none() {
+            let mut loaded = Index::default();
+            for entry in self.entries()? {
+                loaded.add(&entry);
+            }
+            *index = Some(loaded);
+        }
+        Ok(f(index.as_ref().unwrap()))
+    }
+
+    fn entries(&self) -> Result<Vec<AuditEntry>, Box<dyn std::error::Error>> {
+        let f = match File::open(&self.path) {
+            Ok(f) => f,
+            Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(Box::new(err)),
+        };
+        let mut entries = vec![];
+        for line in BufReader::new(f).lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                entries.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// The successfully sent actions matching `filter` that can be, and have not been, reverted.
+    pub fn revertible<F: Fn(&AuditEntry) -> bool>(
+        &self,
+        filter: F,
+    ) -> Result<Vec<AuditEntry>, Box<dyn std::error::Error>> {
+        self.with_index(|index| {
+            index
+                .revertible
+                .iter()
+                .filter(|e| filter(e))
+                .cloned()
+                .collect()
+        })
+    }
+
+    /// Whether the rule `rule` caught `username`, or sent an action on them.
+    pub fn caught(&self, rule: &str, username: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        self.with_index(|index| {
+            index
+                .caught
+                .contains(&(rule.to_owned(), username.to_lowercase()))
+        })
+    }
+}
@@ -1 +1,3 @@
+pub mod audit;
+pub mod pending;
 pub mod rules;
@@ -0,0 +1,362 @@
+use crate::chat::{ActionResult, Chat, Notification};
+use crate::http::HttpsClient;
+use crate::metrics;
+use crate::signup::audit::{AuditEntry, AuditEvent, AuditLog};
+use crate::status::StatusPing;
+use bytes::Bytes;
+use chrono::prelude::*;
+use http_body_util::Full;
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE};
+use hyper::Request;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tokio::time::sleep;
+
+/// A mod action waiting for its delay.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PendingAction {
+    /// The rule and user the action is for. Not in files saved before they were added.
+    #[serde(default)]
+    pub rule: String,
+    #[serde(default)]
+    pub username: String,
+    pub action: String,
+    pub endpoint: String,
+    pub body: Option<String>,
+    pub due: DateTime<Utc>,
+    /// The endpoint that undoes the action, if it can be undone.
+    #[serde(default)]
+    pub revert: Option<String>,
+}
+
+impl PendingAction {
+    fn request(&self, token: &str) -> Request<Full<Bytes>> {
+        self.request_to(&self.endpoint, token)
+    }
+
+    fn request_to(&self, endpoint: &str, token: &str) -> Request<Full<Bytes>> {
+        let mut req = Request::post(endpoint).header(AUTHORIZATION, "Bearer ".to_owned() + token);
+        if self.body.is_some() {
+            req = req.header(CONTENT_TYPE, "application/x-www-form-urlencoded");
+        }
+        req.body(Full::new(Bytes::from(
+            self.body.clone().unwrap_or_default(),
+        )))
+        .unwrap()
+    }
+}
+
+/// Sends mod actions once their delay is over. On shutdown, the actions still waiting are saved
+/// to `path`, and they are scheduled again on the next start. Sent, cancelled and reverted
+/// actions are recorded in the audit trail.
+pub struct PendingActions {
+    path: String,
+    audit: Arc<AuditLog>,
+    token: &'static str,
+    client: HttpsClient,
+    waiting: Arc<Mutex<HashMap<u64, PendingAction>>>,
+    /// Actions out of `waiting` whose request is not done yet. They can't be cancelled anymore,
+    /// and can't be reverted yet.
+    sending: Arc<Mutex<HashMap<u64, PendingAction>>>,
+    next_id: u64,
+    tasks: JoinSet<()>,
+    shutdown_tx: watch::Sender<bool>,
+    status_tx: UnboundedSender<StatusPing>,
+    chat: Chat,
+}
+
+impl PendingActions {
+    pub fn new(
+        path: String,
+        audit: Arc<AuditLog>,
+        token: &'static str,
+        client: HttpsClient,
+        status_tx: UnboundedSender<StatusPing>,
+        chat: Chat,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let saved: Vec<PendingAction> = match File::open(&path) {
+            Ok(f) => serde_json::from_reader(f)?,
+            Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(err) => return Err(Box::new(err)),
+        };
+
+        let mut pending = PendingActions {
+            path,
+            audit,
+            token,
+            client,
+            waiting: Arc::new(Mutex::new(HashMap::new())),
+            sending: Arc::new(Mutex::new(HashMap::new())),
+            next_id: 0,
+            tasks: JoinSet::new(),
+            shutdown_tx: watch::channel(false).0,
+            status_tx,
+            chat,
+        };
+        if !saved.is_empty() {
+            info!("Rescheduling {} saved pending actions.", saved.len());
+            for action in saved {
+                pending.schedule(action);
+            }
+            fs::remove_file(&pending.path)?;
+        }
+        Ok(pending)
+    }
+
+    pub fn schedule(&mut self, action: PendingAction) {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let delay = (action.due - Utc::now())
+            .to_std()
+            .unwrap_or(Duration::from_millis(0));
+        let count = {
+            let mut waiting = self.waiting.lock().unwrap();
+            waiting.insert(id, action);
+            waiting.len()
+        };
+        let _ = self.status_tx.send(StatusPing::PendingActions(count));
+
+        let waiting = self.waiting.clone();
+        let sending = self.sending.clone();
+        let client = self.client.clone();
+        let token = self.token;
+        let mut shutdown_rx = self.shutdown_tx.subscribe();
+        let status_tx = self.status_tx.clone();
+        let chat = self.chat.clone();
+        let audit = self.audit.clone();
+
+        self.tasks.spawn(async move {
+            if delay > Duration::from_millis(0) {
+                tokio::select! {
+                    _ = sleep(delay) => {}
+                    _ = shutdown_rx.changed() => {}
+                }
+            }
+
+            // Gone if it was cancelled, or saved by `shutdown`, in the meantime.
+            let action = {
+                let mut waiting = waiting.lock().unwrap();
+                match waiting.remove(&id) {
+                    Some(action) => {
+                        let _ = status_tx.send(StatusPing::PendingActions(waiting.len()));
+                        sending.lock().unwrap().insert(id, action.clone());
+                        action
+                    }
+                    None => return,
+                }
+            };
+
+            let (status, ok) = match client.request(action.request(token)).await {
+                Ok(res) => {
+                    info!(
+                        rule = %action.rule,
+                        username = %action.username,
+                        action = %action.action,
+                        endpoint = %action.endpoint,
+                        status = res.status().as_u16(),
+                        "Action sent."
+                    );
+                    (res.status().as_u16().to_string(), res.status().is_success())
+                }
+                Err(err) => {
+                    error!(
+                        rule = %action.rule,
+                        username = %action.username,
+                        action = %action.action,
+                        endpoint = %action.endpoint,
+                        %err,
+                        "Error on mod action."
+                    );
+                    ("error".to_owned(), false)
+                }
+            };
+            let _ = status_tx.send(StatusPing::ActionSent { ok });
+            metrics::ACTIONS_SENT
+                .with_label_values(&[&action.action, &status])
+                .inc();
+            // Recorded before it leaves `sending`, so that `revertible` sees it in either.
+            let action = {
+                let mut sending = sending.lock().unwrap();
+                // The rule may have been renamed in the meantime.
+                let action = sending.remove(&id).unwrap_or(action);
+                record(&audit, &action, AuditEvent::Sent, &status, ok);
+                action
+            };
+            chat.notify(Notification::Action(ActionResult {
+                rule: action.rule,
+                username: action.username,
+                action: action.action,
+                status,
+                ok,
+            }));
+        });
+
+        while self.tasks.try_join_next().is_some() {}
+    }
+
+    /// Cancels the actions matching `filter` that are still waiting for their delay. Returns how
+    /// many there were.
+    pub fn cancel<F: Fn(&PendingAction) -> bool>(&mut self, filter: F) -> usize {
+        let mut waiting = self.waiting.lock().unwrap();
+        let cancelled: Vec<u64> = waiting
+            .iter()
+            .filter(|(_, action)| filter(action))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in &cancelled {
+            if let Some(action) = waiting.remove(id) {
+                record(&self.audit, &action, AuditEvent::Cancelled, "", true);
+            }
+        }
+        let _ = self
+            .status_tx
+            .send(StatusPing::PendingActions(waiting.len()));
+        cancelled.len()
+    }
+
+    /// Moves the waiting and sending actions of the rule `rule` to the rule `new_name`.
+    pub fn rename_rule(&self, rule: &str, new_name: &str) {
+        let mut waiting = self.waiting.lock().unwrap();
+        let mut sending = self.sending.lock().unwrap();
+        for action in waiting.values_mut().chain(sending.values_mut()) {
+            if action.rule == rule {
+                action.rule = new_name.to_owned();
+            }
+        }
+    }
+
+    /// The sent actions matching `sent` that can still be reverted, and how many of the actions
+    /// matching `sending` are being sent, too late to cancel and too early to revert.
+    pub fn revertible<F, G>(
+        &self,
+        sent: F,
+        sending: G,
+    ) -> Result<(Vec<AuditEntry>, usize), Box<dyn std::error::Error>>
+    where
+        F: Fn(&AuditEntry) -> bool,
+        G: Fn(&PendingAction) -> bool,
+    {
+        let in_flight = self.sending.lock().unwrap();
+        let revertible = self.audit.revertible(sent)?;
+        Ok((
+            revertible,
+            in_flight.values().filter(|action| sending(action)).count(),
+        ))
+    }
+
+    /// Sends the requests undoing `sent`, and posts how it went, about `what`, to `channel`.
+    pub fn revert(&mut self, sent: Vec<AuditEntry>, what: String, channel: &'static str) {
+        let client = self.client.clone();
+        let token = self.token;
+        let audit = self.audit.clone();
+        let chat = self.chat.clone();
+
+        self.tasks.spawn(async move {
+            let mut failures = vec![];
+            for entry in &sent {
+                let action = PendingAction {
+                    rule: entry.rule.clone(),
+                    username: entry.username.clone(),
+                    action: entry.action.clone(),
+                    endpoint: entry.revert.clone().unwrap_or_default(),
+                    body: None,
+                    due: Utc::now(),
+                    revert: None,
+                };
+                let (status, ok) = match client.request(action.request(token)).await {
+                    Ok(res) => (res.status().as_u16().to_string(), res.status().is_success()),
+                    Err(err) => {
+                        error!(
+                            rule = %action.rule,
+                            username = %action.username,
+                            action = %action.action,
+                            endpoint = %action.endpoint,
+                            %err,
+                            "Error on reverting mod action."
+                        );
+                        ("error".to_owned(), false)
+                    }
+                };
+                info!(
+                    rule = %action.rule,
+                    username = %action.username,
+                    action = %action.action,
+                    %status,
+                    "Action reverted."
+                );
+                metrics::ACTIONS_REVERTED
+                    .with_label_values(&[&action.action, &status])
+                    .inc();
+                record(&audit, &action, AuditEvent::Reverted, &status, ok);
+                if !ok {
+                    failures.push(format!(
+                        "{} on {} ({})",
+                        action.action, action.username, status
+                    ));
+                }
+            }
+
+            let mut message = format!(
+                "Reverted {} of {} actions on {}.",
+                sent.len() - failures.len(),
+                sent.len(),
+                what
+            );
+            if !failures.is_empty() {
+                message.push_str(&format!(" Failed: {}.", failures.join(", ")));
+            }
+            chat.post_message(message, channel);
+        });
+    }
+
+    /// Saves the actions still waiting for their delay, and waits for the ones already being
+    /// sent. Returns the number of saved actions.
+    pub async fn shutdown(mut self) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut waiting: Vec<PendingAction> = self
+            .waiting
+            .lock()
+            .unwrap()
+            .drain()
+            .map(|(_, action)| action)
+            .collect();
+        let _ = self.shutdown_tx.send(true);
+        while self.tasks.join_next().await.is_some() {}
+
+        if !waiting.is_empty() {
+            waiting.sort_by_key(|action| action.due);
+            let f = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&self.path)?;
+            serde_json::to_writer(f, &waiting)?;
+        }
+        Ok(waiting.len())
+    }
+}
+
+fn record(audit: &AuditLog, action: &PendingAction, event: AuditEvent, status: &str, ok: bool) {
+    let entry = AuditEntry {
+        at: Utc::now(),
+        event,
+        rule: action.rule.clone(),
+        username: action.username.clone(),
+        action: action.action.clone(),
+        status: status.to_owned(),
+        ok,
+        revert: match event {
+            AuditEvent::Sent => action.revert.clone(),
+            _ => None,
+        },
+        renamed_to: None,
+    };
+    if let Err(err) = audit.record(&entry) {
+        error!(%err, "Error on writing to the audit trail.");
+    }
+}
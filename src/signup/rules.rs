@@ -1,21 +1,43 @@
-use event::{FingerPrint, Ip, User, Username};
-use lua;
+use crate::event::{FingerPrint, Ip, User, Username};
+use crate::lua;
+use chrono::prelude::*;
 use regex::Regex;
-use rlua;
 use std::fs::{File, OpenOptions};
+use url::form_urlencoded;
+
+/// What marking a user as a false positive of a rule did.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FalsePositive {
+    Marked,
+    AlreadyMarked,
+    /// The rule didn't catch the user, so they weren't marked.
+    NotCaught,
+}
 
 pub struct SignupRulesManager {
     pub rules: Vec<Rule>,
     rules_path: String,
+    pub exemptions: Vec<Exemption>,
+    exemptions_path: String,
 }
 
 impl SignupRulesManager {
-    pub fn new(rules_path: String) -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn new(
+        rules_path: String,
+        exemptions_path: String,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
         let f = File::open(&rules_path)?;
         let r = serde_json::from_reader(f)?;
+        let e = match File::open(&exemptions_path) {
+            Ok(f) => serde_json::from_reader(f)?,
+            Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(err) => return Err(Box::new(err)),
+        };
         Ok(SignupRulesManager {
             rules: r,
-            rules_path: rules_path,
+            rules_path,
+            exemptions: e,
+            exemptions_path,
         })
     }
 
@@ -33,9 +55,10 @@ impl SignupRulesManager {
     }
 
     pub fn add_rule(&mut self, rule: Rule) -> Result<(), Box<dyn std::error::Error>> {
+        // Quoted names can hold whitespace.
+        rule.validate()?;
         if self.find_rule(rule.name.clone()).is_some() {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
+            return Err(Box::new(std::io::Error::other(
                 "Already a rule found with that name.",
             )));
         }
@@ -68,10 +91,7 @@ impl SignupRulesManager {
                 self.save()?;
                 Ok(counter)
             }
-            _ => Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Invalid regex.",
-            ))),
+            _ => Err(Box::new(std::io::Error::other("Invalid regex."))),
         }
     }
 
@@ -105,21 +125,22 @@ impl SignupRulesManager {
             .rules
             .iter()
             .position(|r| r.name.eq(&name))
-            .ok_or(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Index could not be found.",
-            ))?;
+            .ok_or(std::io::Error::other("Index could not be found."))?;
         {
-            let rule = self.rules.get_mut(index).ok_or(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Failed to find rule for index.",
-            ))?;
+            let rule = self
+                .rules
+                .get_mut(index)
+                .ok_or(std::io::Error::other("Failed to find rule for index."))?;
 
             if rule.most_recent_caught.contains(&user.0) {
                 return Ok(());
             }
 
             rule.match_count += 1;
+            rule.last_match = Some(Utc::now());
+            if rule.first_match.is_none() {
+                rule.first_match = rule.last_match;
+            }
             let mrc = &mut rule.most_recent_caught;
             let Username(user) = user;
             mrc.push(user.to_owned());
@@ -129,6 +150,211 @@ impl SignupRulesManager {
         }
         self.save()
     }
+
+    /// Marks `username` as wrongly caught by the rule `name`, if the rule caught them: `caught`
+    /// tells whether the audit trail has the match, and the rule's recent matches count too.
+    /// Returns the rule and what happened, or `None` if there is no such rule.
+    pub fn mark_false_positive(
+        &mut self,
+        name: &str,
+        username: &str,
+        caught: bool,
+    ) -> Result<Option<(&Rule, FalsePositive)>, Box<dyn std::error::Error>> {
+        let index = match self.rules.iter().position(|r| r.name.eq(name)) {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+        let rule = &mut self.rules[index];
+        let outcome = if rule
+            .false_positives
+            .iter()
+            .any(|u| u.eq_ignore_ascii_case(username))
+        {
+            FalsePositive::AlreadyMarked
+        } else if caught
+            || rule
+                .most_recent_caught
+                .iter()
+                .any(|u| u.eq_ignore_ascii_case(username))
+        {
+            rule.false_positives.push(username.to_owned());
+            self.save()?;
+            FalsePositive::Marked
+        } else {
+            FalsePositive::NotCaught
+        };
+        Ok(Some((&self.rules[index], outcome)))
+    }
+
+    /// Edits the rule `name` in place, keeping its statistics, and records the change. Returns
+    /// the description of the change, or `None` if there is no such rule.
+    pub fn edit_rule(
+        &mut self,
+        name: &str,
+        edit: RuleEdit,
+    ) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let index = match self.rules.iter().position(|r| r.name.eq(name)) {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+        if let RuleEdit::Rename(new_name) = &edit {
+            if new_name != name && self.find_rule(new_name.clone()).is_some() {
+                return Err("Already a rule found with that name.".into());
+            }
+        }
+
+        let mut edited = self.rules[index].clone();
+        let change = edit.apply(&mut edited);
+        edited.validate()?;
+        edited.changes.push(RuleChange {
+            at: Utc::now(),
+            change: change.clone(),
+        });
+        self.rules[index] = edited;
+        self.save()?;
+        Ok(Some(change))
+    }
+
+    /// The rules whose names match `pattern`, or all of them.
+    pub fn export(&self, pattern: Option<&str>) -> Result<Vec<&Rule>, regex::Error> {
+        let re = match pattern {
+            Some(pattern) => Some(Regex::new(pattern)?),
+            None => None,
+        };
+        Ok(self
+            .rules
+            .iter()
+            .filter(|r| re.as_ref().is_none_or(|re| re.is_match(&r.name)))
+            .collect())
+    }
+
+    /// Adds `rules`, handling names that are taken as `on_collision` says. Only previews the
+    /// import unless `apply`. Imported rules start without statistics, except that a replaced
+    /// rule keeps those of the rule it replaces.
+    pub fn import(
+        &mut self,
+        rules: Vec<Rule>,
+        on_collision: Collision,
+        apply: bool,
+    ) -> Result<ImportReport, Box<dyn std::error::Error>> {
+        let mut report = ImportReport {
+            added: vec![],
+            replaced: vec![],
+            renamed: vec![],
+            skipped: vec![],
+        };
+        let mut result = self.rules.clone();
+        for mut rule in rules {
+            rule.validate()?;
+            rule.reset_stats();
+            match result.iter().position(|r| r.name == rule.name) {
+                None => {
+                    report.added.push(rule.name.clone());
+                    result.push(rule);
+                }
+                Some(_) if on_collision == Collision::Skip => report.skipped.push(rule.name),
+                Some(index) if on_collision == Collision::Replace => {
+                    rule.keep_stats(&result[index]);
+                    rule.changes.push(RuleChange {
+                        at: Utc::now(),
+                        change: "replaced by an imported rule".to_owned(),
+                    });
+                    report.replaced.push(rule.name.clone());
+                    result[index] = rule;
+                }
+                Some(_) => {
+                    let mut n = 1;
+                    let new_name = loop {
+                        let candidate = match n {
+                            1 => format!("{}-imported", rule.name),
+                            _ => format!("{}-imported-{}", rule.name, n),
+                        };
+                        if !result.iter().any(|r| r.name == candidate) {
+                            break candidate;
+                        }
+                        n += 1;
+                    };
+                    report.renamed.push((rule.name.clone(), new_name.clone()));
+                    rule.name = new_name;
+                    result.push(rule);
+                }
+            }
+        }
+        if apply {
+            self.rules = result;
+            self.save()?;
+        }
+        Ok(report)
+    }
+
+    /// Writes the rules and exemptions, for shutdown.
+    pub fn flush(&self) -> Result<(), Box<dyn std::error::Error>> {
+        self.save()?;
+        self.save_exemptions()
+    }
+
+    fn save_exemptions(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let f = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&self.exemptions_path)?;
+        serde_json::to_writer(f, &self.exemptions)?;
+        Ok(())
+    }
+
+    pub fn add_exemption(
+        &mut self,
+        exemption: Exemption,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if self.exemptions.iter().any(|e| e.name.eq(&exemption.name)) {
+            return Err(Box::new(std::io::Error::other(
+                "Already an exemption found with that name.",
+            )));
+        }
+        self.exemptions.push(exemption);
+        self.save_exemptions()
+    }
+
+    pub fn remove_exemption(&mut self, name: String) -> Result<bool, Box<dyn std::error::Error>> {
+        let before = self.exemptions.len();
+        self.exemptions.retain(|e| !e.name.eq(&name));
+        let after = self.exemptions.len();
+        self.save_exemptions()?;
+        Ok(before != after)
+    }
+
+    pub fn list_exemptions(&self) -> Vec<String> {
+        self.exemptions.iter().map(|e| e.friendly()).collect()
+    }
+
+    /// Returns the name of the first unexpired exemption that matches the user, if any.
+    /// Lua errors in an exemption are logged and the exemption is treated as not matching.
+    pub fn matching_exemption(&self, user: &User, lua_state: &rlua::Lua) -> Option<String> {
+        self.exemptions
+            .iter()
+            .filter(|e| !e.expired())
+            .find(|e| match e.criterion.take_action(user, None, lua_state) {
+                Ok(matches) => matches,
+                Err(err) => {
+                    warn!(exemption = %e.name, %err, "Error evaluating exemption.");
+                    false
+                }
+            })
+            .map(|e| e.name.clone())
+    }
+
+    pub fn exempted(&mut self, name: String) -> Result<(), Box<dyn std::error::Error>> {
+        {
+            let exemption = self
+                .exemptions
+                .iter_mut()
+                .find(|e| e.name.eq(&name))
+                .ok_or(std::io::Error::other("Exemption could not be found."))?;
+            exemption.match_count += 1;
+        }
+        self.save_exemptions()
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -146,6 +372,321 @@ pub struct Rule {
     pub enabled: bool,
     #[serde(default = "default_ip_susp")]
     pub susp_ip: bool,
+    #[serde(default = "default_weight")]
+    pub weight: i32,
+    #[serde(default)]
+    pub delay: Option<Delay>,
+    #[serde(default)]
+    pub action_delays: Vec<(String, Delay)>,
+    /// Caught users that moderators marked as wrongly caught.
+    #[serde(default)]
+    pub false_positives: Vec<String>,
+    #[serde(default)]
+    pub first_match: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub last_match: Option<DateTime<Utc>>,
+    /// Edits of the rule, oldest first.
+    #[serde(default)]
+    pub changes: Vec<RuleChange>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RuleChange {
+    pub at: DateTime<Utc>,
+    pub change: String,
+}
+
+/// A change to a rule that keeps its statistics.
+#[derive(Deserialize, Clone)]
+pub enum RuleEdit {
+    Actions(Vec<Action>),
+    Criterion(Criterion),
+    NoDelay(bool),
+    SuspIp(bool),
+    Weight(i32),
+    /// `None` goes back to the configured delays.
+    Delay(Option<Delay>),
+    Rename(String),
+}
+
+impl RuleEdit {
+    /// Applies the edit, and describes it.
+    fn apply(self, rule: &mut Rule) -> String {
+        match self {
+            RuleEdit::Actions(actions) => {
+                let change = format!("actions changed from {:?} to {:?}", rule.actions, actions);
+                rule.actions = actions;
+                change
+            }
+            RuleEdit::Criterion(criterion) => {
+                let change = format!(
+                    "criterion changed from {} to {}",
+                    rule.criterion.friendly(),
+                    criterion.friendly()
+                );
+                rule.criterion = criterion;
+                change
+            }
+            RuleEdit::NoDelay(no_delay) => {
+                rule.no_delay = no_delay;
+                format!("nodelay turned {}", if no_delay { "on" } else { "off" })
+            }
+            RuleEdit::SuspIp(susp_ip) => {
+                rule.susp_ip = susp_ip;
+                format!(
+                    "suspicious IPs only turned {}",
+                    if susp_ip { "on" } else { "off" }
+                )
+            }
+            RuleEdit::Weight(weight) => {
+                let change = format!("weight changed from {:+} to {:+}", rule.weight, weight);
+                rule.weight = weight;
+                change
+            }
+            RuleEdit::Delay(delay) => {
+                rule.delay = delay;
+                match delay {
+                    Some(delay) => format!("delay set to {}", delay.friendly()),
+                    None => "delay reset to the defaults".to_owned(),
+                }
+            }
+            RuleEdit::Rename(name) => {
+                let change = format!("renamed from {} to {}", rule.name, name);
+                rule.name = name;
+                change
+            }
+        }
+    }
+}
+
+impl Rule {
+    /// Per-action delays of the rule win over its own delay, which wins over `no_delay`
+    /// and then the configured defaults.
+    pub fn delay_for(&self, action: &Action, defaults: &DelayDefaults) -> Delay {
+        let key = action.key();
+        if let Some((_, delay)) = self.action_delays.iter().find(|(k, _)| k.eq(key)) {
+            return *delay;
+        }
+        if let Some(delay) = self.delay {
+            return delay;
+        }
+        if self.no_delay {
+            return Delay::Immediate;
+        }
+        defaults.for_action(action)
+    }
+
+    /// The share of matches that were not marked as false positives.
+    pub fn precision(&self) -> Option<f64> {
+        if self.match_count == 0 {
+            return None;
+        }
+        let wrong = self.false_positives.len().min(self.match_count);
+        Some((self.match_count - wrong) as f64 / self.match_count as f64)
+    }
+
+    /// Matches per day since the first one, counting at least a day.
+    pub fn matches_per_day(&self) -> Option<f64> {
+        let days = (Utc::now() - self.first_match?).num_seconds() as f64 / 86400.0;
+        Some(self.match_count as f64 / days.max(1.0))
+    }
+
+    /// Whether the rule matched often enough to tell that its precision is too low.
+    pub fn low_precision(&self, warning: &PrecisionWarning) -> bool {
+        self.match_count >= warning.min_matches
+            && self.precision().is_some_and(|p| p < warning.below)
+    }
+
+    /// Every field of the rule, for `signup rules show`.
+    pub fn friendly(&self, delays: &DelayDefaults, endpoints: &Endpoints) -> String {
+        format!(
+            "Criterion: {}.\nActions: {:?}\nDelays: {}\n\
+             Rule {} is {}{}. Weight: {:+}.\n\
+             {} matches. Recent matches: {}\n{}\n{}",
+            self.criterion.friendly(),
+            self.actions,
+            self.friendly_delays(delays),
+            self.name,
+            if self.enabled { "enabled" } else { "disabled" },
+            if self.susp_ip {
+                ", for suspicious IPs only"
+            } else {
+                ""
+            },
+            self.weight,
+            self.match_count,
+            if self.most_recent_caught.is_empty() {
+                "none".to_owned()
+            } else {
+                self.most_recent_caught
+                    .iter()
+                    .map(|u| endpoints.profile_link(u))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            },
+            self.friendly_stats(),
+            self.friendly_changes(3)
+        )
+    }
+
+    /// Checks what the JSON format allows but commands don't, for imported rules. Added rules are
+    /// checked too, for what the command parser lets through.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.is_empty() || self.name.contains(char::is_whitespace) {
+            return Err(format!("Invalid rule name `{}`", self.name));
+        }
+        if self.actions.is_empty() && self.weight == 0 {
+            return Err(format!("Rule {} has no actions and no weight", self.name));
+        }
+        if let Criterion::ScoreRange(min, Some(max)) = self.criterion {
+            if min > max {
+                return Err(format!(
+                    "Score range {}..{} of {} never matches",
+                    min, max, self.name
+                ));
+            }
+        }
+        for (key, delay) in &self.action_delays {
+            if !ACTION_KEYS.contains(&key.as_str()) {
+                return Err(format!(
+                    "Unknown action `{}` in delays of {}",
+                    key, self.name
+                ));
+            }
+            delay.validate()?;
+        }
+        if let Some(delay) = self.delay {
+            delay.validate()?;
+        }
+        Ok(())
+    }
+
+    /// Clears what the rule learnt from matches, for a rule that is new here.
+    fn reset_stats(&mut self) {
+        self.match_count = 0;
+        self.most_recent_caught = vec![];
+        self.false_positives = vec![];
+        self.first_match = None;
+        self.last_match = None;
+        self.changes = vec![];
+    }
+
+    /// Takes over the statistics of `old`, for a rule replacing it.
+    fn keep_stats(&mut self, old: &Rule) {
+        self.match_count = old.match_count;
+        self.most_recent_caught = old.most_recent_caught.clone();
+        self.false_positives = old.false_positives.clone();
+        self.first_match = old.first_match;
+        self.last_match = old.last_match;
+        self.changes = old.changes.clone();
+    }
+
+    /// The latest changes, one per line.
+    pub fn friendly_changes(&self, count: usize) -> String {
+        if self.changes.is_empty() {
+            return "Never edited.".to_owned();
+        }
+        let latest: Vec<String> = self
+            .changes
+            .iter()
+            .rev()
+            .take(count)
+            .map(|c| format!("{}: {}", c.at.format("%d/%m/%Y %T (UTC)"), c.change))
+            .collect();
+        format!("Latest edits:\n{}", latest.join("\n"))
+    }
+
+    pub fn friendly_stats(&self) -> String {
+        format!(
+            "Precision: {} ({} false positives of {} matches). Matches per day: {}. Last match: {}.",
+            friendly_percent(self.precision()),
+            self.false_positives.len(),
+            self.match_count,
+            self.matches_per_day()
+                .map_or("none".to_owned(), |d| format!("{:.1}", d)),
+            friendly_time(self.last_match)
+        )
+    }
+
+    pub fn friendly_delays(&self, defaults: &DelayDefaults) -> String {
+        let delays: Vec<String> = self
+            .actions
+            .iter()
+            .filter(|a| !a.eq(&&Action::NotifySlack))
+            .map(|a| format!("{} {}", a.key(), self.delay_for(a, defaults).friendly()))
+            .collect();
+        if delays.is_empty() {
+            "none".to_owned()
+        } else {
+            delays.join(", ")
+        }
+    }
+
+    /// Whether the rule only counts towards score criteria, having a weight but no actions.
+    pub fn is_signal(&self) -> bool {
+        self.actions.is_empty() && self.weight != 0
+    }
+}
+
+pub fn friendly_percent(share: Option<f64>) -> String {
+    share.map_or("n/a".to_owned(), |s| format!("{:.0}%", s * 100.0))
+}
+
+pub fn friendly_time(time: Option<DateTime<Utc>>) -> String {
+    time.map_or("never".to_owned(), |t| {
+        t.format("%d/%m/%Y %T (UTC)").to_string()
+    })
+}
+
+/// What to do with an imported rule whose name is taken.
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum Collision {
+    Skip,
+    Replace,
+    /// Imports it with `-imported` appended to its name.
+    Rename,
+}
+
+pub struct ImportReport {
+    pub added: Vec<String>,
+    pub replaced: Vec<String>,
+    /// As `(name, new name)`.
+    pub renamed: Vec<(String, String)>,
+    pub skipped: Vec<String>,
+}
+
+impl ImportReport {
+    pub fn friendly(&self) -> String {
+        let mut parts = vec![];
+        let mut list = |what: &str, names: Vec<String>| {
+            if !names.is_empty() {
+                parts.push(format!("{}: {}.", what, names.join(", ")));
+            }
+        };
+        list("Added", self.added.clone());
+        list("Replaced", self.replaced.clone());
+        list(
+            "Renamed",
+            self.renamed
+                .iter()
+                .map(|(old, new)| format!("{} to {}", old, new))
+                .collect(),
+        );
+        list("Skipped (name taken)", self.skipped.clone());
+        if parts.is_empty() {
+            "No rules.".to_owned()
+        } else {
+            parts.join(" ")
+        }
+    }
+}
+
+/// When to warn about rules with many false positives, set in `conf.rs`.
+pub struct PrecisionWarning {
+    /// Precision (from 0 to 1) below which a rule is flagged.
+    pub below: f64,
+    /// Matches a rule needs before it is flagged.
+    pub min_matches: usize,
 }
 
 fn default_match_count() -> usize {
@@ -167,6 +708,174 @@ fn default_ip_susp() -> bool {
     false
 }
 
+fn default_weight() -> i32 {
+    0
+}
+
+/// The longest delay, in milliseconds: a day.
+pub const MAX_DELAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// How long to wait before sending an action to lichess. All durations are in milliseconds.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+pub enum Delay {
+    Immediate,
+    Fixed(u64),
+    Random(u64, u64),
+}
+
+impl Delay {
+    /// `sample` lies in [0, 1) and is drawn once per signup, so random delays of one signup keep
+    /// their relative order: a close delayed by 31.5-181.5 s happens 1.5 s after an engine mark
+    /// delayed by 30-180 s.
+    pub fn millis(&self, sample: f64) -> u64 {
+        match self {
+            Delay::Immediate => 0,
+            Delay::Fixed(ms) => *ms,
+            Delay::Random(min, max) => min + ((max.saturating_sub(*min)) as f64 * sample) as u64,
+        }
+    }
+
+    /// Checks what the JSON format allows but commands don't.
+    pub fn validate(&self) -> Result<(), String> {
+        let (min, max) = match *self {
+            Delay::Immediate => return Ok(()),
+            Delay::Fixed(ms) => (ms, ms),
+            Delay::Random(min, max) => (min, max),
+        };
+        if min > max {
+            return Err(format!(
+                "Delay range {} has its minimum above its maximum",
+                self.friendly()
+            ));
+        }
+        if max > MAX_DELAY_MS {
+            return Err(format!("Delay {} is longer than a day", self.friendly()));
+        }
+        Ok(())
+    }
+
+    pub fn friendly(&self) -> String {
+        match self {
+            Delay::Immediate => "no delay".to_owned(),
+            Delay::Fixed(ms) => format!("{} s", friendly_seconds(*ms)),
+            Delay::Random(min, max) => {
+                format!("{}-{} s", friendly_seconds(*min), friendly_seconds(*max))
+            }
+        }
+    }
+}
+
+fn friendly_seconds(ms: u64) -> String {
+    if ms.is_multiple_of(1000) {
+        (ms / 1000).to_string()
+    } else {
+        (ms as f64 / 1000.0).to_string()
+    }
+}
+
+/// The lichess instance that actions are sent to, set in `conf.rs`.
+pub struct Endpoints {
+    pub base_url: &'static str,
+    /// Endpoint templates replacing `Action::default_endpoint`, by action key, and
+    /// `Action::revert_endpoint`, by `un` and the action key.
+    pub overrides: &'static [(&'static str, &'static str)],
+}
+
+impl Endpoints {
+    pub fn validate(&self) -> Result<(), String> {
+        for (key, _) in self.overrides {
+            let known = match key.strip_prefix("un") {
+                Some(reverted) if REVERTIBLE_KEYS.contains(&reverted) => true,
+                _ => ACTION_KEYS.contains(key) && !key.eq(&"notify"),
+            };
+            if !known {
+                return Err(format!("No endpoint to override for action `{}`", key));
+            }
+        }
+        Ok(())
+    }
+
+    /// The template overriding the endpoint with this key, if any.
+    fn overridden(&self, key: &str) -> Option<&'static str> {
+        self.overrides
+            .iter()
+            .find(|(k, _)| k.eq(&key))
+            .map(|(_, template)| *template)
+    }
+
+    fn fill(&self, template: &str, username: &Username, param: &str) -> String {
+        template
+            .replace("{base}", self.base_url.trim_end_matches('/'))
+            .replace("{username}", &username.0)
+            .replace("{param}", param)
+    }
+
+    pub fn public_profile_url(&self, username: &str) -> String {
+        format!("{}/@/{}", self.base_url.trim_end_matches('/'), username)
+    }
+
+    /// The mod view of the user's profile.
+    pub fn profile_url(&self, username: &str) -> String {
+        format!("{}/@/{}?mod", self.base_url.trim_end_matches('/'), username)
+    }
+
+    /// A link to `profile_url`, in Slack's format. The Zulip backend converts it to Markdown.
+    pub fn profile_link(&self, username: &str) -> String {
+        format!("<{}|{}>", self.profile_url(username), username)
+    }
+}
+
+/// Delays for rules that don't configure their own, set in `conf.rs`.
+pub struct DelayDefaults {
+    pub default: Delay,
+    pub actions: &'static [(&'static str, Delay)],
+}
+
+impl DelayDefaults {
+    pub fn for_action(&self, action: &Action) -> Delay {
+        match self.actions.iter().find(|(k, _)| k.eq(&action.key())) {
+            Some((_, delay)) => *delay,
+            None => self.default,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Exemption {
+    pub name: String,
+    pub criterion: Criterion,
+    #[serde(default)]
+    pub expires: Option<DateTime<Utc>>,
+    #[serde(default = "default_match_count")]
+    pub match_count: usize,
+}
+
+impl Exemption {
+    pub fn expired(&self) -> bool {
+        match self.expires {
+            Some(expires) => expires <= Utc::now(),
+            None => false,
+        }
+    }
+
+    pub fn friendly(&self) -> String {
+        format!(
+            "{}: {}. {} suppressed signups. {}",
+            &self.name,
+            self.criterion.friendly(),
+            self.match_count,
+            match self.expires {
+                None => "Never expires.".to_owned(),
+                Some(expires) => format!(
+                    "{} (UTC) {}.",
+                    if self.expired() { "Expired" } else { "Expires" },
+                    expires.format("%d/%m/%Y %T")
+                ),
+            }
+        )
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub enum Criterion {
     IpMatch(Ip),
@@ -177,15 +886,24 @@ pub enum Criterion {
     UsernameRegex(#[serde(with = "serde_regex")] Regex),
     UseragentLengthLte(usize),
     Lua(String),
+    /// Matches when the signup's score lies in the inclusive range; `None` means unbounded.
+    ScoreRange(i32, Option<i32>),
 }
 
 impl Criterion {
-    pub fn take_action(&self, user: &User, lua_state: &rlua::Lua) -> Result<bool, rlua::Error> {
+    /// `score` is the sum of the weights of the matching rules, or `None` while the score
+    /// is still being computed; score criteria never match without one.
+    pub fn take_action(
+        &self,
+        user: &User,
+        score: Option<i32>,
+        lua_state: &rlua::Lua,
+    ) -> Result<bool, rlua::Error> {
         Ok(match self {
             Criterion::IpMatch(exact) => exact.eq(&user.ip),
             Criterion::PrintMatch(exact) => match user.finger_print {
                 None => false,
-                Some(ref fp) => exact.eq(&fp),
+                Some(ref fp) => exact.eq(fp),
             },
             Criterion::EmailContains(part) => {
                 user.email.0.to_uppercase().contains(&part.to_uppercase())
@@ -199,9 +917,17 @@ impl Criterion {
             Criterion::UsernameRegex(re) => re.is_match(&user.username.0),
             Criterion::UseragentLengthLte(len) => user.user_agent.0.len() <= *len,
             Criterion::Lua(code) => lua::call_constraints_function(code, user.clone(), lua_state)?,
+            Criterion::ScoreRange(min, max) => match score {
+                None => false,
+                Some(score) => score >= *min && max.is_none_or(|max| score <= max),
+            },
         })
     }
 
+    pub fn is_score(&self) -> bool {
+        matches!(self, Criterion::ScoreRange(_, _))
+    }
+
     pub fn friendly(&self) -> String {
         match self {
             Criterion::IpMatch(exact) => format!("IP equals `{}`", exact.0),
@@ -216,10 +942,87 @@ impl Criterion {
                 format!("User agent length is less than or equal to {}", l)
             }
             Criterion::Lua(code) => format!("Lua code `{}` evaluates to true.", code),
+            Criterion::ScoreRange(min, None) => format!("Score is at least {}", min),
+            Criterion::ScoreRange(min, Some(max)) => {
+                format!("Score is between {} and {} (inclusive)", min, max)
+            }
         }
     }
 }
 
+/// Formats the rules that contributed to a signup's score, e.g. `7 (ip-range +5, mail +2)`.
+pub fn friendly_score(breakdown: &[(String, i32)]) -> String {
+    let score: i32 = breakdown.iter().map(|(_, weight)| weight).sum();
+    if breakdown.is_empty() {
+        return score.to_string();
+    }
+    format!(
+        "{} ({})",
+        score,
+        breakdown
+            .iter()
+            .map(|(name, weight)| format!("{} {:+}", name, weight))
+            .collect::<Vec<String>>()
+            .join(", ")
+    )
+}
+
+pub const ACTION_KEYS: &[&str] = &[
+    "shadowban",
+    "engine",
+    "boost",
+    "ipban",
+    "close",
+    "panic",
+    "notify",
+    "note",
+    "warn",
+    "report",
+    "alt",
+    "reportban",
+    "resetrating",
+];
+
+/// The keys of the actions that have a `revert_endpoint`.
+pub const REVERTIBLE_KEYS: &[&str] = &[
+    "shadowban",
+    "engine",
+    "boost",
+    "ipban",
+    "close",
+    "alt",
+    "reportban",
+];
+
+/// Preset warnings for `Action::Warn`, by the name used in Slack commands.
+pub const WARNING_PRESETS: &[(&str, &str)] = &[
+    ("language", "Warning: Offensive language"),
+    ("spam", "Warning: spam is not permitted"),
+    ("accusations", "Warning: Accusations"),
+    ("sandbagging", "Warning: Sandbagging"),
+    ("boosting", "Warning: Boosting"),
+];
+
+pub const REPORT_REASONS: &[&str] = &["cheat", "boost", "comm", "other"];
+
+/// Ratings `Action::ResetRating` can reset, by lichess perf key.
+pub const RATING_PERFS: &[&str] = &[
+    "ultraBullet",
+    "bullet",
+    "blitz",
+    "rapid",
+    "classical",
+    "correspondence",
+    "chess960",
+    "kingOfTheHill",
+    "threeCheck",
+    "antichess",
+    "atomic",
+    "horde",
+    "racingKings",
+    "crazyhouse",
+];
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub enum Action {
     Shadowban,
@@ -229,24 +1032,127 @@ pub enum Action {
     Close,
     EnableChatPanic,
     NotifySlack,
+    /// Adds a mod note. Without text, the note only describes the rule that matched.
+    ModNote(Option<String>),
+    /// Sends the warning with the given subject, one of `WARNING_PRESETS`.
+    Warn(String),
+    /// Reports the user for the given reason, one of `REPORT_REASONS`.
+    Report(String),
+    /// Marks the user as an alt of the given account.
+    AltMark(String),
+    /// Prevents (or allows again) the user from sending reports.
+    ReportBan(bool),
+    /// Resets the user's rating in the given perf, one of `RATING_PERFS`.
+    ResetRating(String),
 }
 
 impl Action {
-    pub fn api_endpoint(&self, username: &Username) -> Option<String> {
+    /// The name of the action as used in Slack commands and configuration.
+    pub fn key(&self) -> &'static str {
+        match self {
+            Action::Shadowban => "shadowban",
+            Action::EngineMark => "engine",
+            Action::BoostMark => "boost",
+            Action::IpBan => "ipban",
+            Action::Close => "close",
+            Action::EnableChatPanic => "panic",
+            Action::NotifySlack => "notify",
+            Action::ModNote(_) => "note",
+            Action::Warn(_) => "warn",
+            Action::Report(_) => "report",
+            Action::AltMark(_) => "alt",
+            Action::ReportBan(_) => "reportban",
+            Action::ResetRating(_) => "resetrating",
+        }
+    }
+
+    /// The endpoint of the action, with `{base}` standing for the lichess URL,
+    /// `{username}` for the user and `{param}` for the parameter of `ReportBan` and
+    /// `ResetRating`.
+    pub fn default_endpoint(&self) -> Option<&'static str> {
         match self {
-            Action::Shadowban => Some(format!("https://lichess.org/mod/{}/troll/true", username.0)),
-            Action::EngineMark => Some(format!(
-                "https://lichess.org/mod/{}/engine/true",
-                username.0
-            )),
-            Action::BoostMark => Some(format!(
-                "https://lichess.org/mod/{}/booster/true",
-                username.0
-            )),
-            Action::IpBan => Some(format!("https://lichess.org/mod/{}/ban/true", username.0)),
-            Action::Close => Some(format!("https://lichess.org/mod/{}/close", username.0)),
-            Action::EnableChatPanic => Some(String::from("https://lichess.org/mod/chat-panic")),
+            Action::Shadowban => Some("{base}/mod/{username}/troll/true"),
+            Action::EngineMark => Some("{base}/mod/{username}/engine/true"),
+            Action::BoostMark => Some("{base}/mod/{username}/booster/true"),
+            Action::IpBan => Some("{base}/mod/{username}/ban/true"),
+            Action::Close => Some("{base}/mod/{username}/close"),
+            Action::EnableChatPanic => Some("{base}/mod/chat-panic"),
             Action::NotifySlack => None,
+            Action::ModNote(_) => Some("{base}/@/{username}/note"),
+            Action::Warn(_) => Some("{base}/mod/{username}/warn"),
+            Action::Report(_) => Some("{base}/report"),
+            Action::AltMark(_) => Some("{base}/mod/{username}/alt/true"),
+            Action::ReportBan(_) => Some("{base}/mod/{username}/reportban/{param}"),
+            Action::ResetRating(_) => Some("{base}/mod/{username}/reset-rating/{param}"),
+        }
+    }
+
+    pub fn api_endpoint(&self, username: &Username, endpoints: &Endpoints) -> Option<String> {
+        let template = self.default_endpoint()?;
+        let template = endpoints.overridden(self.key()).unwrap_or(template);
+        let param = match self {
+            Action::ReportBan(enabled) => enabled.to_string(),
+            Action::ResetRating(perf) => perf.clone(),
+            _ => String::new(),
+        };
+        Some(endpoints.fill(template, username, &param))
+    }
+
+    /// The endpoint that undoes the action, like `default_endpoint`. Notes, warnings, reports,
+    /// rating resets and chat panic can't be undone.
+    pub fn revert_endpoint(&self) -> Option<&'static str> {
+        match self {
+            Action::Shadowban => Some("{base}/mod/{username}/troll/false"),
+            Action::EngineMark => Some("{base}/mod/{username}/engine/false"),
+            Action::BoostMark => Some("{base}/mod/{username}/booster/false"),
+            Action::IpBan => Some("{base}/mod/{username}/ban/false"),
+            Action::Close => Some("{base}/mod/{username}/reopen"),
+            Action::AltMark(_) => Some("{base}/mod/{username}/alt/false"),
+            Action::ReportBan(_) => Some("{base}/mod/{username}/reportban/{param}"),
+            _ => None,
         }
     }
+
+    pub fn api_revert_endpoint(
+        &self,
+        username: &Username,
+        endpoints: &Endpoints,
+    ) -> Option<String> {
+        let template = self.revert_endpoint()?;
+        let template = endpoints
+            .overridden(&format!("un{}", self.key()))
+            .unwrap_or(template);
+        let param = match self {
+            Action::ReportBan(enabled) => (!enabled).to_string(),
+            _ => String::new(),
+        };
+        Some(endpoints.fill(template, username, &param))
+    }
+
+    /// The form-encoded body of the action request, if it needs one.
+    pub fn api_body(&self, username: &Username, rule: &Rule) -> Option<String> {
+        let context = format!("signup rule {}: {}", &rule.name, rule.criterion.friendly());
+        let mut form = form_urlencoded::Serializer::new(String::new());
+        match self {
+            Action::ModNote(text) => form
+                .append_pair(
+                    "text",
+                    &match text {
+                        Some(text) => format!("{} ({})", text, context),
+                        None => format!("Matched {}", context),
+                    },
+                )
+                .append_pair("mod", "true"),
+            Action::Warn(subject) => form.append_pair("subject", subject),
+            Action::Report(reason) => form
+                .append_pair("username", &username.0)
+                .append_pair("reason", reason)
+                .append_pair("text", &format!("Matched {}", context)),
+            Action::AltMark(main) => {
+                form.append_pair("text", &format!("Alt of {}, matched {}", main, context))
+            }
+            _ => return None,
+        };
+        Some(form.finish())
+    }
 }
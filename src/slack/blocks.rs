@@ -0,0 +1,68 @@
+use crate::chat::{ButtonValue, MatchMessage, MATCH_BUTTONS};
+
+/// A match message as Block Kit blocks, with buttons if `interactive`.
+pub fn match_blocks(message: &MatchMessage, interactive: bool) -> serde_json::Value {
+    let mut fields = vec![
+        mrkdwn(format!("*Criterion*\n{}", message.criterion)),
+        mrkdwn(format!(
+            "*Actions*\n{}",
+            if message.actions.is_empty() {
+                "none".to_owned()
+            } else {
+                message.actions.join(", ")
+            }
+        )),
+        mrkdwn(format!("*Previous matches*\n{}", message.previous_matches)),
+    ];
+    if let Some(score) = &message.score {
+        fields.push(mrkdwn(format!("*Score*\n{}", score)));
+    }
+
+    let mut blocks = vec![
+        json!({
+            "type": "section",
+            "text": mrkdwn(format!(
+                "*Rule {} match:* <{}|{}> (<{}|mod view>)",
+                message.rule, message.profile_url, message.username, message.mod_url
+            )),
+        }),
+        json!({ "type": "section", "fields": fields }),
+        json!({
+            "type": "context",
+            "elements": [mrkdwn(format!(
+                "Recent matches: {}",
+                if message.recent_matches.is_empty() {
+                    "none".to_owned()
+                } else {
+                    message.recent_matches.join(", ")
+                }
+            ))],
+        }),
+    ];
+
+    if interactive {
+        let value = json!(ButtonValue {
+            rule: message.rule.clone(),
+            username: message.username.clone(),
+        })
+        .to_string();
+        let buttons: Vec<serde_json::Value> = MATCH_BUTTONS
+            .iter()
+            .map(|(action_id, label)| {
+                json!({
+                    "type": "button",
+                    "action_id": action_id,
+                    "text": { "type": "plain_text", "text": label },
+                    "value": value,
+                })
+            })
+            .collect();
+        blocks.push(json!({ "type": "actions", "elements": buttons }));
+    }
+
+    json!(blocks)
+}
+
+fn mrkdwn(text: String) -> serde_json::Value {
+    json!({ "type": "mrkdwn", "text": text })
+}
@@ -0,0 +1,45 @@
+use crate::chat::{Command, Listener};
+use crate::slack::event::MessageEvent;
+use crate::slack::web::Web;
+use async_trait::async_trait;
+
+/// How the bot receives messages from Slack.
+#[derive(Clone, Copy)]
+pub enum SlackConnection {
+    /// Socket Mode, with an app-level token (`xapp-...`) that has the `connections:write`
+    /// scope. The app must be subscribed to `app_mention` events.
+    SocketMode { app_token: &'static str },
+    /// The legacy RTM API, for classic Slack apps.
+    #[allow(dead_code)] // Only used if chosen in conf.rs.
+    Rtm,
+}
+
+/// A way of connecting to Slack.
+#[async_trait]
+pub trait Transport: Send + Sync {
+    /// Connects, and handles incoming messages until the connection closes.
+    async fn run(
+        &self,
+        web: Web,
+        bot_id: &'static str,
+        listener: &Listener,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+}
+
+/// The command in `message`, if a user sent it in the listen channel, mentioning the bot first.
+pub fn command(message: &MessageEvent, bot_id: &str, listener: &Listener) -> Option<Command> {
+    let bot_ping = format!("<@{}> ", bot_id);
+    if !message.is_from_user()
+        || message.channel != listener.channel
+        || !message.text.starts_with(&bot_ping)
+    {
+        return None;
+    }
+
+    Some(Command {
+        author: message.user.clone().unwrap_or_default(),
+        channel: message.channel.clone(),
+        thread: message.reply_thread(),
+        text: message.text[bot_ping.len()..].to_owned(),
+    })
+}
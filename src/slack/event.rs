@@ -1,16 +1,36 @@
+/// A message, as sent over RTM and in `message` and `app_mention` events. Edits, deletions
+/// and bot messages have a `subtype` or a `bot_id`, and may lack `user` or `text`.
+#[derive(Deserialize)]
+pub struct MessageEvent {
+    pub user: Option<String>,
+    #[serde(default)]
+    pub text: String,
+    pub channel: String,
+    pub ts: Option<String>,
+    pub thread_ts: Option<String>,
+    pub subtype: Option<String>,
+    pub bot_id: Option<String>,
+}
+
+impl MessageEvent {
+    /// Whether a user wrote this message, as opposed to it being an edit, a bot message, etc.
+    pub fn is_from_user(&self) -> bool {
+        self.user.is_some() && self.subtype.is_none() && self.bot_id.is_none()
+    }
+
+    /// The thread to reply in: the message's thread, or a new one under the message.
+    pub fn reply_thread(&self) -> Option<String> {
+        self.thread_ts.clone().or_else(|| self.ts.clone())
+    }
+}
+
 #[derive(Deserialize)]
 #[serde(tag = "type")]
 pub enum RtmRecv {
     #[serde(rename = "message")]
-    Message {
-        user: String,
-        text: String,
-        client_msg_id: String,
-        team: String,
-        channel: String,
-        event_ts: String,
-        ts: String,
-    },
+    Message(MessageEvent),
+    #[serde(other)]
+    Other,
 }
 
 #[derive(Serialize)]
@@ -20,4 +40,65 @@ pub struct RtmSend {
     pub type_: String,
     pub channel: String,
     pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread_ts: Option<String>,
+}
+
+/// A Socket Mode message. Envelopes (those with an `envelope_id`) must be acknowledged.
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+pub enum SocketModeRecv {
+    #[serde(rename = "hello")]
+    Hello,
+    /// Slack is about to close the connection, e.g. to refresh it.
+    #[serde(rename = "disconnect")]
+    Disconnect { reason: Option<String> },
+    #[serde(rename = "events_api")]
+    EventsApi { payload: EventCallback },
+    #[serde(rename = "interactive")]
+    Interactive { payload: InteractivePayload },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+pub struct EventCallback {
+    pub event: EventsApiEvent,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+pub enum EventsApiEvent {
+    /// Mentions also arrive as `message` events; only these are commands.
+    #[serde(rename = "app_mention")]
+    AppMention(MessageEvent),
+    /// Any message in a channel the bot is in, edits, bot messages and thread replies included.
+    #[serde(rename = "message")]
+    Message(MessageEvent),
+    #[serde(other)]
+    Other,
+}
+
+/// A button click or other interaction with a message the bot posted.
+#[derive(Deserialize)]
+pub struct InteractivePayload {
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub user: InteractiveId,
+    /// Where the message with the button is, for `block_actions`.
+    pub channel: Option<InteractiveId>,
+    #[serde(default)]
+    pub actions: Vec<BlockAction>,
+}
+
+#[derive(Deserialize)]
+pub struct InteractiveId {
+    pub id: String,
+}
+
+#[derive(Deserialize)]
+pub struct BlockAction {
+    pub action_id: String,
+    #[serde(default)]
+    pub value: String,
 }
@@ -1,4 +1,85 @@
-mod command;
-mod event;
-pub mod rtm;
+mod blocks;
+mod connection;
+pub mod event;
+mod rtm;
+mod socket_mode;
 pub mod web;
+
+pub use self::connection::SlackConnection;
+
+use crate::chat::{Backend, Command, Listener, MatchMessage};
+use crate::slack::connection::Transport;
+use crate::slack::rtm::Rtm;
+use crate::slack::socket_mode::SocketMode;
+use crate::slack::web::Web;
+use async_trait::async_trait;
+
+#[derive(Clone, Copy)]
+pub struct SlackConfig {
+    pub api_url: &'static str,
+    pub bot_token: &'static str,
+    pub bot_user_id: &'static str,
+    pub connection: SlackConnection,
+}
+
+pub struct Slack {
+    web: Web,
+    bot_user_id: &'static str,
+    transport: Box<dyn Transport>,
+    /// Whether button clicks reach the bot, which they only do in Socket Mode.
+    interactive: bool,
+}
+
+impl Slack {
+    pub fn new(config: SlackConfig) -> Slack {
+        let transport: Box<dyn Transport> = match config.connection {
+            SlackConnection::SocketMode { app_token } => Box::new(SocketMode { app_token }),
+            SlackConnection::Rtm => Box::new(Rtm),
+        };
+        Slack {
+            web: Web {
+                api_url: config.api_url,
+                token: config.bot_token,
+            },
+            bot_user_id: config.bot_user_id,
+            transport,
+            interactive: match config.connection {
+                SlackConnection::SocketMode { .. } => true,
+                SlackConnection::Rtm => false,
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl Backend for Slack {
+    async fn send_message(&self, text: String, channel: &str) {
+        self.web.send_message(text, channel, None).await
+    }
+
+    async fn send_match(&self, message: &MatchMessage, channel: &str) {
+        let blocks = blocks::match_blocks(message, self.interactive);
+        self.web
+            .send_blocks(message.text.clone(), blocks, channel)
+            .await
+    }
+
+    async fn send_file(&self, content: String, filename: &str, channel: &str) {
+        self.web.upload_file(content, filename, channel).await
+    }
+
+    async fn reply(&self, text: String, command: &Command) {
+        self.web
+            .send_message(text, &command.channel, command.thread.as_deref())
+            .await
+    }
+
+    async fn listen(
+        &self,
+        listener: &Listener,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.transport
+            .run(self.web, self.bot_user_id, listener)
+            .await
+    }
+}
@@ -0,0 +1,134 @@
+use crate::chat::{Button, Listener};
+use crate::http;
+use crate::slack::connection::{self, Transport};
+use crate::slack::event::{EventsApiEvent, SocketModeRecv};
+use crate::slack::web::Web;
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::{SinkExt, StreamExt};
+use http_body_util::{BodyExt, Full};
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE};
+use hyper::Request;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+/// Socket Mode: events arrive over the WebSocket and are acknowledged there, replies are posted
+/// with the Web API.
+pub struct SocketMode {
+    pub app_token: &'static str,
+}
+
+#[async_trait]
+impl Transport for SocketMode {
+    async fn run(
+        &self,
+        slack_web: Web,
+        bot_id: &'static str,
+        listener: &Listener,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let req = Request::post(slack_web.method_url("apps.connections.open"))
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .header(AUTHORIZATION, "Bearer ".to_owned() + self.app_token)
+            .body(Full::new(Bytes::new()))?;
+
+        let res = http::client().request(req).await?;
+        let body = res.into_body().collect().await?.to_bytes();
+
+        let resp: serde_json::Value = serde_json::from_slice(&body)?;
+        let ws_url = match &resp["url"] {
+            serde_json::Value::String(s) => s.clone(),
+            _ => {
+                return Err(format!(
+                    "apps.connections.open returned no URL: {}",
+                    String::from_utf8_lossy(&body)
+                )
+                .into())
+            }
+        };
+
+        let (mut socket, _) = connect_async(ws_url).await?;
+        listener.connected()?;
+
+        while let Some(msg) = socket.next().await {
+            let text = match msg? {
+                Message::Text(text) => text,
+                Message::Ping(_) => {
+                    listener.ping()?;
+                    continue;
+                }
+                _ => continue,
+            };
+            listener.ping()?;
+
+            let envelope: serde_json::Value = match serde_json::from_str(&text) {
+                Ok(envelope) => envelope,
+                Err(err) => {
+                    warn!(%err, "Could not parse Socket Mode message.");
+                    continue;
+                }
+            };
+            if let Some(envelope_id) = envelope["envelope_id"].as_str() {
+                socket
+                    .send(Message::Text(
+                        json!({ "envelope_id": envelope_id }).to_string().into(),
+                    ))
+                    .await?;
+            }
+
+            match serde_json::from_value(envelope) {
+                Ok(SocketModeRecv::Hello) => info!("Socket Mode connection initialized."),
+                Ok(SocketModeRecv::Disconnect { reason }) => {
+                    info!(?reason, "Slack asked to reconnect.");
+                    return Ok(());
+                }
+                Ok(SocketModeRecv::EventsApi { payload }) => match payload.event {
+                    EventsApiEvent::AppMention(message) => {
+                        if let Some(reply) = connection::command(&message, bot_id, listener)
+                            .and_then(|command| listener.command(&command))
+                        {
+                            let thread_ts = message.reply_thread();
+                            let channel = message.channel;
+                            tokio::spawn(async move {
+                                slack_web
+                                    .send_message(reply, &channel, thread_ts.as_deref())
+                                    .await
+                            });
+                        }
+                    }
+                    EventsApiEvent::Message(message) => debug!(
+                        channel = %message.channel,
+                        subtype = ?message.subtype,
+                        thread_ts = ?message.thread_ts,
+                        bot_id = ?message.bot_id,
+                        "Message event."
+                    ),
+                    EventsApiEvent::Other => {}
+                },
+                Ok(SocketModeRecv::Interactive { payload }) => {
+                    let channel = payload.channel.map(|c| c.id).unwrap_or_default();
+                    if payload.type_ != "block_actions" || channel != listener.channel {
+                        info!(type_ = %payload.type_, %channel, "Ignoring interactive payload.");
+                        continue;
+                    }
+                    for action in payload.actions {
+                        let button = Button {
+                            author: payload.user.id.clone(),
+                            action_id: action.action_id,
+                            value: action.value,
+                        };
+                        if let Some(reply) = listener.button(&button) {
+                            let channel = channel.clone();
+                            tokio::spawn(async move {
+                                slack_web.send_message(reply, &channel, None).await
+                            });
+                        }
+                    }
+                }
+                Ok(SocketModeRecv::Other) => {}
+                Err(err) => warn!(%err, "Could not parse Socket Mode envelope."),
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,42 +1,124 @@
-use futures::future;
-use hyper::header::HeaderValue;
-use hyper::rt::Future;
-use hyper::{Body, Client, Method, Request};
-use hyper_tls::HttpsConnector;
-use tokio;
-
-pub fn post_message(text: String, token: &'static str, channel: &'static str) {
-    tokio::spawn(future::lazy(move || {
-        let https = HttpsConnector::new(2).unwrap();
-        let client = Client::builder().build::<_, Body>(https);
+use crate::http;
+use crate::metrics;
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE};
+use hyper::Request;
+use url::form_urlencoded;
 
-        let content = json!({
+/// The Slack Web API, at `api_url` so that it can be pointed at a mock server.
+#[derive(Clone, Copy)]
+pub struct Web {
+    pub api_url: &'static str,
+    pub token: &'static str,
+}
+
+impl Web {
+    pub fn method_url(&self, method: &str) -> String {
+        format!("{}/{}", self.api_url.trim_end_matches('/'), method)
+    }
+
+    /// Posts `text` to `channel`, in the thread `thread_ts` if there is one.
+    pub async fn send_message(&self, text: String, channel: &str, thread_ts: Option<&str>) {
+        let mut content = json!({
             "channel": channel,
             "text": text
-        })
-        .to_string();
+        });
+        if let Some(thread_ts) = thread_ts {
+            content["thread_ts"] = json!(thread_ts);
+        }
+        self.post_message(content, channel).await
+    }
 
-        let mut req = Request::new(Body::from(content));
+    /// Posts Block Kit `blocks` to `channel`, with `text` for notifications.
+    pub async fn send_blocks(&self, text: String, blocks: serde_json::Value, channel: &str) {
+        let content = json!({
+            "channel": channel,
+            "text": text,
+            "blocks": blocks
+        });
+        self.post_message(content, channel).await
+    }
 
-        *req.uri_mut() = "https://slack.com/api/chat.postMessage"
-            .to_owned()
-            .parse()
+    async fn post_message(&self, content: serde_json::Value, channel: &str) {
+        let req = Request::post(self.method_url("chat.postMessage"))
+            .header(CONTENT_TYPE, "application/json")
+            .header(AUTHORIZATION, "Bearer ".to_owned() + self.token)
+            .body(Full::new(Bytes::from(content.to_string())))
             .unwrap();
-        *req.method_mut() = Method::POST;
-
-        req.headers_mut().insert(
-            hyper::header::CONTENT_TYPE,
-            HeaderValue::from_static("application/json"),
-        );
-
-        req.headers_mut().insert(
-            hyper::header::AUTHORIZATION,
-            HeaderValue::from_str(&("Bearer ".to_owned() + token)).unwrap(),
-        );
-
-        client
-            .request(req)
-            .map(|_| {})
-            .map_err(|err| println!("Error in post_message: {}", err))
-    }));
+
+        // Slack answers most failures, like `channel_not_found`, with a 200 and `"ok": false`.
+        if let Err(err) = self.call(req).await {
+            metrics::CHAT_POST_FAILURES.inc();
+            error!(channel, %err, "Error in chat.postMessage.");
+        }
+    }
+
+    /// Uploads `content` as a file shared in `channel`, with the external upload flow: get an
+    /// upload URL, send the content there, then complete the upload.
+    pub async fn upload_file(&self, content: String, filename: &str, channel: &str) {
+        if let Err(err) = self.try_upload_file(content, filename, channel).await {
+            metrics::CHAT_POST_FAILURES.inc();
+            error!(channel, filename, %err, "Error on uploading file to Slack.");
+        }
+    }
+
+    async fn try_upload_file(
+        &self,
+        content: String,
+        filename: &str,
+        channel: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let form = form_urlencoded::Serializer::new(String::new())
+            .append_pair("filename", filename)
+            .append_pair("length", &content.len().to_string())
+            .finish();
+        let upload = self
+            .call(
+                Request::post(self.method_url("files.getUploadURLExternal"))
+                    .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+                    .header(AUTHORIZATION, "Bearer ".to_owned() + self.token)
+                    .body(Full::new(Bytes::from(form)))?,
+            )
+            .await?;
+        let (upload_url, file_id) =
+            match (upload["upload_url"].as_str(), upload["file_id"].as_str()) {
+                (Some(url), Some(id)) => (url.to_owned(), id.to_owned()),
+                _ => return Err("files.getUploadURLExternal returned no upload URL.".into()),
+            };
+
+        let res = http::client()
+            .request(Request::post(upload_url).body(Full::new(Bytes::from(content)))?)
+            .await?;
+        if !res.status().is_success() {
+            return Err(format!("File upload responded with {}.", res.status()).into());
+        }
+
+        let complete = json!({
+            "files": [{ "id": file_id, "title": filename }],
+            "channel_id": channel,
+        });
+        self.call(
+            Request::post(self.method_url("files.completeUploadExternal"))
+                .header(CONTENT_TYPE, "application/json")
+                .header(AUTHORIZATION, "Bearer ".to_owned() + self.token)
+                .body(Full::new(Bytes::from(complete.to_string())))?,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Calls a Web API method, and returns the response unless it is not `"ok"`.
+    async fn call(
+        &self,
+        req: Request<Full<Bytes>>,
+    ) -> Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>> {
+        let res = http::client().request(req).await?;
+        let body = res.into_body().collect().await?.to_bytes();
+        let resp: serde_json::Value = serde_json::from_slice(&body)?;
+        if resp["ok"] != true {
+            return Err(format!("Slack API error: {}", String::from_utf8_lossy(&body)).into());
+        }
+        Ok(resp)
+    }
 }
@@ -1,81 +1,171 @@
-use conf;
-use event::Event;
-use eventstream;
-use futures::future::{self, loop_fn, Loop};
-use futures::Future;
-use slack;
-use std::sync::mpsc::{Receiver, Sender};
+use crate::alerts::{AlertConfig, Alerts};
+use crate::backoff::BackoffPolicy;
+use crate::chat::{self, Chat, Notification};
+use crate::event::Event;
+use crate::eventstream;
+use crate::health::{Connection, Health, HealthReport};
 use std::time::{Duration, Instant};
-use tokio;
-use tokio::timer::Delay;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
+use tokio::time::sleep;
 
 pub enum StatusPing {
+    /// Any data on the event stream, keep-alive newlines included.
     StreamEventReceived,
+    /// An actual event on the event stream, by type.
+    EventReceived(String),
     EnsureAliveConnectionLichess,
-    EnsureAliveConnectionSlack,
-    SlackPingReceived,
+    EnsureAliveConnectionChat,
+    ChatPingReceived,
+    Connected(Connection),
+    Disconnected(Connection, String),
+    PendingActions(usize),
+    ActionSent {
+        ok: bool,
+    },
+    Rules {
+        total: usize,
+        enabled: usize,
+    },
+    LuaError {
+        rule: String,
+        username: String,
+        error: String,
+    },
+    Report(oneshot::Sender<HealthReport>),
 }
 
+/// What `status_loop` needs from the configuration.
+pub struct StatusConfig {
+    pub lichess_url: &'static str,
+    pub token: &'static str,
+    pub chat_channel: &'static str,
+    pub notify_channel: &'static str,
+    pub alerts: AlertConfig,
+    pub backoff: BackoffPolicy,
+    /// How long the event stream may stay silent before its watcher is restarted.
+    pub stream_timeout: Duration,
+}
+
+/// Supervises the lichess and chat connections: starts them, and replaces them when they go
+/// silent. Also keeps the health state and checks it for alerts.
 pub fn status_loop(
-    rx: Receiver<StatusPing>,
-    main_tx: Sender<Event>,
-    token: &'static str,
-    status_tx: Sender<StatusPing>,
+    mut rx: UnboundedReceiver<StatusPing>,
+    main_tx: UnboundedSender<Event>,
+    status_tx: UnboundedSender<StatusPing>,
+    chat: Chat,
+    config: StatusConfig,
 ) {
-    tokio::spawn(future::loop_fn(
-        (Instant::now(), Instant::now()),
-        move |(latest_stream_event, latest_slack_event)| {
-            let ping = rx.recv().unwrap();
+    let StatusConfig {
+        lichess_url,
+        token,
+        chat_channel,
+        notify_channel,
+        alerts: alert_config,
+        backoff: backoff_policy,
+        stream_timeout,
+    } = config;
+    tokio::spawn(async move {
+        let mut latest_stream_event = Instant::now();
+        let mut latest_chat_event = Instant::now();
+        let mut health = Health::new();
+        let mut alerts = Alerts::new(alert_config, stream_timeout);
+
+        let start_stream_watcher = || {
+            eventstream::watch_event_stream(
+                main_tx.clone(),
+                lichess_url,
+                token,
+                status_tx.clone(),
+                backoff_policy,
+            )
+        };
+        let start_chat_connection = || {
+            chat::connect(
+                chat.clone(),
+                chat_channel,
+                main_tx.clone(),
+                status_tx.clone(),
+                backoff_policy,
+            )
+        };
+        let mut stream_watcher = start_stream_watcher();
+        let mut chat_connection = start_chat_connection();
 
+        while let Some(ping) = rx.recv().await {
             match ping {
                 StatusPing::StreamEventReceived => {
-                    Ok(Loop::Continue((Instant::now(), latest_slack_event)))
+                    latest_stream_event = Instant::now();
+                    health.stream_data_received();
                 }
                 StatusPing::EnsureAliveConnectionLichess => {
-                    if latest_stream_event.elapsed().as_secs() > 90 {
-                        eventstream::watch_event_stream(main_tx.clone(), token, status_tx.clone());
-                        println!("Event stream watcher restarted.");
-                        Ok(Loop::Continue((Instant::now(), latest_slack_event)))
-                    } else {
-                        Ok(Loop::Continue((latest_stream_event, latest_slack_event)))
+                    // Posted by `alerts` once the stream goes quiet, not on every restart.
+                    if latest_stream_event.elapsed() > stream_timeout {
+                        stream_watcher.abort();
+                        stream_watcher = start_stream_watcher();
+                        health.disconnected(
+                            Connection::Lichess,
+                            format!("No data for {} seconds.", stream_timeout.as_secs()),
+                        );
+                        warn!("Event stream watcher restarted.");
+                        latest_stream_event = Instant::now();
                     }
                 }
-                StatusPing::SlackPingReceived => {
-                    Ok(Loop::Continue((latest_stream_event, Instant::now())))
-                }
-                StatusPing::EnsureAliveConnectionSlack => {
-                    if latest_slack_event.elapsed().as_secs() > 720 {
-                        slack::rtm::connect_to_slack(
-                            conf::SLACK_BOT_TOKEN,
-                            conf::SLACK_BOT_USER_ID,
-                            conf::SLACK_CHANNEL,
-                            main_tx.clone(),
-                            status_tx.clone(),
-                        );
-                        println!("Slack connection restarted.");
-                        Ok(Loop::Continue((latest_stream_event, Instant::now())))
-                    } else {
-                        Ok(Loop::Continue((latest_stream_event, latest_slack_event)))
+                StatusPing::EventReceived(event_type) => health.event_received(&event_type),
+                StatusPing::ChatPingReceived => latest_chat_event = Instant::now(),
+                StatusPing::EnsureAliveConnectionChat => {
+                    if latest_chat_event.elapsed().as_secs() > 720 {
+                        chat_connection.abort();
+                        chat_connection = start_chat_connection();
+                        health
+                            .disconnected(Connection::Chat, "No ping for 720 seconds.".to_owned());
+                        warn!("Chat connection restarted.");
+                        latest_chat_event = Instant::now();
                     }
                 }
+                StatusPing::Connected(connection) => health.connected(connection),
+                StatusPing::Disconnected(connection, error) => {
+                    health.disconnected(connection, error)
+                }
+                StatusPing::PendingActions(count) => health.pending_actions(count),
+                StatusPing::ActionSent { ok } => health.action_sent(ok),
+                StatusPing::Rules { total, enabled } => health.rules(total, enabled),
+                StatusPing::LuaError {
+                    rule,
+                    username,
+                    error,
+                } => health.lua_error(rule, username, error),
+                StatusPing::Report(report_tx) => {
+                    let _ = report_tx.send(health.report());
+                }
+            }
+
+            for message in alerts.check(&health) {
+                warn!("{}", message);
+                chat.notify(Notification::Alert {
+                    text: message.clone(),
+                });
+                chat.post_message(message, notify_channel);
             }
-        },
-    ));
+        }
+    });
 }
 
-pub fn periodically_ensure_alive_connection(status_tx: Sender<StatusPing>) {
-    tokio::spawn(loop_fn((), move |_| {
-        let status_tx2 = status_tx.clone();
-        Delay::new(Instant::now() + Duration::from_secs(15))
-            .and_then(move |_| {
-                status_tx2
-                    .send(StatusPing::EnsureAliveConnectionLichess)
-                    .unwrap();
-                status_tx2
-                    .send(StatusPing::EnsureAliveConnectionSlack)
-                    .unwrap();
-                Ok(Loop::Continue(()))
-            })
-            .map_err(|e| println!("Err in periodically_...: {}", e))
-    }));
+/// Has the status loop check every `every` that the connections are still alive.
+pub fn periodically_ensure_alive_connection(
+    status_tx: UnboundedSender<StatusPing>,
+    every: Duration,
+) {
+    tokio::spawn(async move {
+        loop {
+            sleep(every).await;
+            if status_tx
+                .send(StatusPing::EnsureAliveConnectionLichess)
+                .and_then(|_| status_tx.send(StatusPing::EnsureAliveConnectionChat))
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
 }
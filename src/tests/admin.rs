@@ -0,0 +1,116 @@
+use crate::http;
+use crate::metrics;
+use crate::tests::{rule, wait_for, Bot};
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::{Request, StatusCode};
+
+async fn get(addr: &str, path: &str) -> (StatusCode, String) {
+    let req = Request::get(format!("http://{}{}", addr, path))
+        .body(Full::new(Bytes::new()))
+        .unwrap();
+    let res = http::client().request(req).await.unwrap();
+    let status = res.status();
+    let body = res.into_body().collect().await.unwrap().to_bytes();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn metrics_count_matches_and_actions() {
+    let bot = Bot::start(json!([rule(
+        "metrics-raid",
+        json!({ "IpMatch": "10.7.7.7" }),
+        json!(["Shadowban"]),
+    )]))
+    .await;
+
+    bot.signup("Innocent", "innocent@example.com", "10.0.0.7")
+        .await;
+    bot.signup("Raider", "raider@example.com", "10.7.7.7").await;
+    bot.lichess.wait_for_actions(1).await;
+
+    // Other tests run in the same process, so only this test's rule has exact counts.
+    let scraped = wait_for("the action to be counted", || {
+        let scraped = metrics::render();
+        if scraped.contains("mod_actions_sent_total{action=\"shadowban\",status=\"200\"}") {
+            Some(scraped)
+        } else {
+            None
+        }
+    })
+    .await;
+    assert!(scraped.contains("signup_rule_evaluations_total{rule=\"metrics-raid\"} 2"));
+    assert!(scraped.contains("signup_rule_matches_total{rule=\"metrics-raid\"} 1"));
+    assert!(scraped.contains("signup_rule_evaluation_seconds_count{rule=\"metrics-raid\"} 2"));
+    assert!(scraped.contains("lichess_events_received_total{type=\"signup\"}"));
+
+    let (_, served) = get(&bot.admin_addr, "/metrics").await;
+    assert!(served.contains("signup_rule_matches_total{rule=\"metrics-raid\"} 1"));
+}
+
+/// The value of an unlabelled counter in the scraped metrics.
+fn counter(name: &str) -> u64 {
+    metrics::render()
+        .lines()
+        .find_map(|line| line.strip_prefix(name)?.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+#[tokio::test]
+async fn slack_errors_count_as_post_failures() {
+    let bot = Bot::start(json!([])).await;
+    bot.chat.fail_posts(true);
+    let before = counter("chat_post_failures_total ");
+
+    bot.command("status").await;
+    bot.chat.wait_for_message("I am alive!").await;
+    // Other tests run in the same process, so the counter only has a lower bound.
+    wait_for("the failure to be counted", || {
+        if counter("chat_post_failures_total ") > before {
+            Some(())
+        } else {
+            None
+        }
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn health_reports_connections_and_rules() {
+    let bot = Bot::start(json!([
+        rule(
+            "enabled",
+            json!({ "IpMatch": "10.0.0.1" }),
+            json!(["Shadowban"])
+        ),
+        json!({
+            "name": "disabled",
+            "criterion": { "IpMatch": "10.0.0.2" },
+            "actions": ["Shadowban"],
+            "enabled": false,
+        }),
+    ]))
+    .await;
+    bot.signup("Someone", "someone@example.com", "10.0.0.3")
+        .await;
+
+    // `wait_for` takes a synchronous check, so poll by hand.
+    let mut report = serde_json::Value::Null;
+    for _ in 0..500 {
+        let (status, body) = get(&bot.admin_addr, "/health").await;
+        report = serde_json::from_str(&body).unwrap();
+        if status == StatusCode::OK && report["latest_event"].is_string() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_eq!(report["lichess"]["connected"], true);
+    assert_eq!(report["chat"]["connected"], true);
+    assert_eq!(report["rules"], 2);
+    assert_eq!(report["enabled_rules"], 1);
+
+    bot.command("status").await;
+    let (_, text) = bot.chat.wait_for_message("I am alive!").await;
+    assert!(text.contains("Lichess event stream: connected for"));
+    assert!(text.contains("Rules: 2 (1 enabled)."));
+}
@@ -0,0 +1,178 @@
+use crate::alerts::AlertConfig;
+use crate::tests::{quiet_alerts, rule, wait_for, Bot, Options, NOTIFY_CHANNEL};
+use std::time::Duration;
+
+#[tokio::test]
+async fn lua_error_storm_alerts_once_and_recovers() {
+    let alerts = AlertConfig {
+        max_lua_errors: 2,
+        lua_errors_window: Duration::from_secs(2),
+        ..quiet_alerts()
+    };
+    let bot = start(
+        alerts,
+        json!([rule(
+            "broken",
+            json!({ "Lua": "user:name():find('Storm') ~= nil and regex('', '(')" }),
+            json!(["Shadowban"]),
+        )]),
+    )
+    .await;
+
+    for i in 1..5 {
+        bot.signup(&format!("Storm{}", i), "storm@example.com", "10.0.0.1")
+            .await;
+    }
+    let (channel, text) = bot.chat.wait_for_message("Alert: ").await;
+    assert_eq!(channel, NOTIFY_CHANNEL);
+    assert!(text.contains("Lua errors in the last 2 seconds"));
+    bot.discord
+        .wait_for_embed("Lua errors in the last 2 seconds")
+        .await;
+
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    bot.signup("Calm", "calm@example.com", "10.0.0.1").await;
+    bot.chat.wait_for_message("Resolved: Lua errors.").await;
+
+    let alerts: Vec<(String, String)> = bot
+        .chat
+        .messages()
+        .into_iter()
+        .filter(|(_, text)| text.starts_with("Alert: "))
+        .collect();
+    assert_eq!(alerts.len(), 1);
+}
+
+async fn start(alerts: AlertConfig, rules: serde_json::Value) -> Bot {
+    Options {
+        alerts,
+        ..Options::new(rules)
+    }
+    .start()
+    .await
+}
+
+/// How many alerts containing `what` were posted.
+fn alert_count(bot: &Bot, what: &str) -> usize {
+    bot.chat
+        .messages()
+        .iter()
+        .filter(|(_, text)| text.starts_with("Alert: ") && text.contains(what))
+        .count()
+}
+
+/// Has the alerts checked, by asking for the status.
+async fn check_alerts(bot: &Bot) {
+    let reports = |bot: &Bot| {
+        bot.chat
+            .messages()
+            .iter()
+            .filter(|(_, text)| text.starts_with("I am alive!"))
+            .count()
+    };
+    let before = reports(bot);
+    bot.command("status").await;
+    wait_for("a status report", || {
+        if reports(bot) > before {
+            Some(())
+        } else {
+            None
+        }
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn no_signups_alert_once_and_recover() {
+    let alerts = AlertConfig {
+        no_signups_for: Duration::from_secs(1),
+        ..quiet_alerts()
+    };
+    let bot = start(alerts, json!([])).await;
+
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    check_alerts(&bot).await;
+    let (channel, _) = bot.chat.wait_for_message("Alert: no signups for").await;
+    assert_eq!(channel, NOTIFY_CHANNEL);
+    check_alerts(&bot).await;
+
+    bot.signup("Newcomer", "newcomer@example.com", "10.0.0.1")
+        .await;
+    bot.chat.wait_for_message("Resolved: no signups.").await;
+    assert_eq!(alert_count(&bot, "no signups"), 1);
+}
+
+#[tokio::test]
+async fn reconnect_storm_alerts_once_and_recovers() {
+    let alerts = AlertConfig {
+        max_reconnects: 1,
+        reconnects_window: Duration::from_secs(2),
+        ..quiet_alerts()
+    };
+    let bot = start(alerts, json!([])).await;
+
+    for connections in 1..4 {
+        wait_for("the bot to connect to the event stream", || {
+            if bot.lichess.connections() >= connections {
+                Some(())
+            } else {
+                None
+            }
+        })
+        .await;
+        if connections < 3 {
+            bot.lichess.disconnect();
+        }
+    }
+    let (channel, _) = bot
+        .chat
+        .wait_for_message("Alert: 2 lichess event stream reconnects")
+        .await;
+    assert_eq!(channel, NOTIFY_CHANNEL);
+
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    check_alerts(&bot).await;
+    bot.chat
+        .wait_for_message("Resolved: lichess event stream reconnects.")
+        .await;
+    assert_eq!(alert_count(&bot, "reconnects"), 1);
+}
+
+#[tokio::test]
+async fn action_failures_alert_once_and_recover() {
+    let alerts = AlertConfig {
+        max_action_failure_percent: 50,
+        min_actions: 2,
+        actions_window: Duration::from_secs(2),
+        ..quiet_alerts()
+    };
+    let bot = start(
+        alerts,
+        json!([rule(
+            "raid",
+            json!({ "UsernameContains": "raider" }),
+            json!(["Shadowban"]),
+        )]),
+    )
+    .await;
+
+    bot.lichess.fail_actions(true);
+    for i in 1..4 {
+        bot.signup(&format!("Raider{}", i), "raider@example.com", "10.0.0.1")
+            .await;
+    }
+    let (channel, _) = bot
+        .chat
+        .wait_for_message("Alert: 2 of the last 2 actions failed.")
+        .await;
+    assert_eq!(channel, NOTIFY_CHANNEL);
+    bot.lichess.wait_for_actions(3).await;
+    bot.lichess.fail_actions(false);
+
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    check_alerts(&bot).await;
+    bot.chat
+        .wait_for_message("Resolved: action failures.")
+        .await;
+    assert_eq!(alert_count(&bot, "actions failed"), 1);
+}
@@ -0,0 +1,56 @@
+use crate::backoff::{Backoff, BackoffPolicy, RetryPolicy};
+use std::time::Duration;
+
+const POLICY: BackoffPolicy = BackoffPolicy {
+    initial: Duration::from_secs(1),
+    max: Duration::from_secs(10),
+    jitter: 0.0,
+    stable_after: Duration::from_secs(60),
+};
+
+#[test]
+fn delay_doubles_up_to_max_and_resets_after_stable_connection() {
+    let mut backoff = Backoff::new(POLICY);
+    let delays: Vec<u64> = (0..6)
+        .map(|_| backoff.next_delay(Duration::from_secs(1)).as_secs())
+        .collect();
+    assert_eq!(delays, vec![1, 2, 4, 8, 10, 10]);
+
+    assert_eq!(backoff.next_delay(Duration::from_secs(60)), POLICY.initial);
+    assert_eq!(backoff.next_delay(Duration::ZERO).as_secs(), 2);
+}
+
+#[test]
+fn jitter_only_shortens_delay() {
+    let mut backoff = Backoff::new(BackoffPolicy {
+        jitter: 0.5,
+        ..POLICY
+    });
+    for _ in 0..20 {
+        let delay = backoff.next_delay(Duration::from_secs(60));
+        assert!(delay <= POLICY.initial && delay > POLICY.initial / 2);
+    }
+}
+
+#[test]
+fn retry_delay_keeps_growing() {
+    let mut backoff = Backoff::retrying(RetryPolicy {
+        initial: Duration::from_millis(50),
+        max: Duration::from_millis(400),
+        jitter: 0.0,
+    });
+    let delays: Vec<u128> = (0..5)
+        .map(|_| backoff.next_retry_delay().as_millis())
+        .collect();
+    assert_eq!(delays, vec![50, 100, 200, 400, 400]);
+}
+
+#[test]
+fn out_of_range_jitter_is_clamped() {
+    for jitter in [-0.5, 1.5, f64::NAN] {
+        let mut backoff = Backoff::new(BackoffPolicy { jitter, ..POLICY });
+        for _ in 0..20 {
+            assert!(backoff.next_delay(Duration::from_secs(60)) <= POLICY.initial);
+        }
+    }
+}
@@ -0,0 +1,588 @@
+use crate::logging::{self, LogFormat};
+use crate::tests::mock::{MockChat, MockLichess, MockSlack};
+use crate::tests::{rule, wait_for, Bot, Options, BOT_ID, CHANNEL};
+
+#[tokio::test]
+async fn list_rules() {
+    let bot = Bot::start(json!([
+        rule(
+            "first",
+            json!({ "IpMatch": "10.0.0.1" }),
+            json!(["Shadowban"])
+        ),
+        rule(
+            "second",
+            json!({ "IpMatch": "10.0.0.2" }),
+            json!(["Shadowban"])
+        ),
+    ]))
+    .await;
+
+    bot.command("signup rules list").await;
+
+    let (channel, _) = bot
+        .chat
+        .wait_for_message("Current rules: first, second")
+        .await;
+    assert_eq!(channel, CHANNEL);
+}
+
+#[tokio::test]
+async fn added_rule_applies_to_next_signup() {
+    let bot = Bot::start(json!([])).await;
+
+    bot.command("signup rules add raiders if username contains raider then shadowban+close")
+        .await;
+    bot.chat.wait_for_message("Rule added!").await;
+
+    bot.signup("TheRaider", "raider@example.com", "10.0.0.1")
+        .await;
+    let mut paths: Vec<String> = bot
+        .lichess
+        .wait_for_actions(2)
+        .await
+        .into_iter()
+        .map(|a| a.path)
+        .collect();
+    paths.sort();
+    assert_eq!(
+        paths,
+        vec!["/mod/TheRaider/close", "/mod/TheRaider/troll/true"]
+    );
+}
+
+#[tokio::test]
+async fn commands_take_quoted_values_code_spans_and_keyword_arguments() {
+    let bot = Bot::start(json!([])).await;
+
+    bot.command("signup rules add \"raid-wave\" if username regex `^raid\\d+$` then none weight=2")
+        .await;
+    bot.chat.wait_for_message("Rule added!").await;
+
+    bot.command("signup rules show “raid-wave”").await;
+    let (_, text) = bot.chat.wait_for_message("Rule raid-wave is").await;
+    assert!(text.contains("Weight: +2."));
+    assert!(text.contains("^raid\\d+$"));
+
+    // Rule names are single words, even quoted.
+    bot.command("signup rules add \"raid wave\" if username contains raid then none weight=2")
+        .await;
+    bot.chat
+        .wait_for_message("Error on adding rule: Invalid rule name `raid wave`")
+        .await;
+}
+
+#[tokio::test]
+async fn parse_errors_point_at_the_offending_token() {
+    let bot = Bot::start(json!([])).await;
+
+    bot.command("signup rules add raiders if username contains raider shadowban")
+        .await;
+    let (_, text) = bot.chat.wait_for_message("Expected `then`").await;
+    let command = "signup rules add raiders if username contains raider shadowban";
+    let carets = format!("{}{}", " ".repeat(command.len() - 9), "^".repeat(9));
+    assert!(text.contains(&format!("```\n{}\n{}\n```", command, carets)));
+    assert!(text.contains("Usage: `signup rules add <name> if"));
+
+    bot.command("signup rules add raiders if username contains raider then shadowbam")
+        .await;
+    bot.chat
+        .wait_for_message("Unknown action `shadowbam`, did you mean `shadowban`?")
+        .await;
+}
+
+#[tokio::test]
+async fn help_lists_and_explains_commands() {
+    let bot = Bot::start(json!([])).await;
+
+    bot.command("help").await;
+    let (_, text) = bot.chat.wait_for_message("`status`: ").await;
+    assert!(text.contains("`signup rules add`: Adds a rule."));
+    assert!(text.contains("`signup revert-rule`: "));
+
+    bot.command("help signup rules add").await;
+    let (_, text) = bot
+        .chat
+        .wait_for_message("`signup rules add <name> if|if_susp_ip <criterion>")
+        .await;
+    assert!(text.contains("Example: `signup rules add raiders if"));
+    assert!(text.contains("`username contains|regex <value>`"));
+    assert!(text.contains("`lua <code>`"));
+    assert!(text.contains("`warn:language|spam|"));
+
+    bot.command("help signup rules ad").await;
+    bot.chat
+        .wait_for_message("No command `signup rules ad`, did you mean `signup rules add`?")
+        .await;
+}
+
+#[tokio::test]
+async fn misspellings_get_suggestions() {
+    let bot = Bot::start(json!([])).await;
+
+    bot.command("signup rulez list").await;
+    bot.chat
+        .wait_for_message("no command `rulez` of `signup`, did you mean `rules`?")
+        .await;
+
+    bot.command("signup rules add raiders if usernme contains raider then close")
+        .await;
+    bot.chat
+        .wait_for_message("Unknown criterion `usernme`, did you mean `username`?")
+        .await;
+
+    bot.command("signup rules add raiders if username contains raider then close nodelya")
+        .await;
+    bot.chat
+        .wait_for_message("Unknown option `nodelya`, did you mean `nodelay`?")
+        .await;
+}
+
+#[tokio::test]
+async fn unparsable_command_gets_rtm_reply() {
+    let bot = Options::new(json!([]))
+        .start_with(MockLichess::start().await, MockSlack::start_rtm().await)
+        .await;
+
+    bot.command("signup rules frobnicate").await;
+
+    bot.chat
+        .wait_for_reply("Could not parse user command")
+        .await;
+}
+
+#[tokio::test]
+async fn messages_in_other_channels_are_ignored() {
+    let bot = Bot::start(json!([rule(
+        "first",
+        json!({ "IpMatch": "10.0.0.1" }),
+        json!(["Shadowban"]),
+    )]))
+    .await;
+
+    bot.command("status").await;
+    bot.chat.send_message("<@UBOT> signup rules list", "COTHER");
+    bot.command("signup rules show first").await;
+
+    bot.chat
+        .wait_for_message("Criterion: IP equals `10.0.0.1`")
+        .await;
+    assert!(bot
+        .chat
+        .messages()
+        .iter()
+        .all(|(_, text)| !text.starts_with("Current rules")));
+}
+
+#[tokio::test]
+async fn rtm_reconnects_after_disconnect() {
+    let bot = Options::new(json!([rule(
+        "first",
+        json!({ "IpMatch": "10.0.0.1" }),
+        json!(["Shadowban"]),
+    )]))
+    .start_with(MockLichess::start().await, MockSlack::start_rtm().await)
+    .await;
+
+    bot.command("status").await;
+    bot.chat.wait_for_message("I am alive!").await;
+    bot.chat.disconnect();
+    wait_for("the bot to reconnect to Slack", || {
+        if bot.chat.connections() > 1 {
+            Some(())
+        } else {
+            None
+        }
+    })
+    .await;
+
+    bot.command("signup rules list").await;
+    bot.chat.wait_for_message("Current rules: first").await;
+}
+
+#[tokio::test]
+async fn log_level_can_be_changed() {
+    // Another test may have installed the logger already.
+    let _ = logging::init("off", LogFormat::Text);
+    let bot = Bot::start(json!([])).await;
+
+    bot.command("log level off,lichess_event_stream=off").await;
+    bot.chat
+        .wait_for_message("Log level set to `off,lichess_event_stream=off`.")
+        .await;
+
+    bot.command("log level lichess_event_stream=nonsense").await;
+    bot.chat.wait_for_message("Invalid log level").await;
+}
+
+#[tokio::test]
+async fn socket_mode_acks_envelopes_and_replies_in_thread() {
+    let bot = Bot::start(json!([])).await;
+    bot.command("status").await;
+    bot.chat.wait_for_message("I am alive!").await;
+
+    let envelope_id = bot.chat.send_event(json!({
+        "type": "app_mention",
+        "user": "U00000001",
+        "text": format!("<@{}> signup rules frobnicate", BOT_ID),
+        "channel": CHANNEL,
+        "ts": "1540000000.000200",
+        "thread_ts": "1540000000.000100",
+    }));
+
+    bot.chat.wait_for_ack(&envelope_id).await;
+    let post = bot.chat.wait_for_post("Could not parse user command").await;
+    assert_eq!(post["channel"], CHANNEL);
+    assert_eq!(post["thread_ts"], "1540000000.000100");
+}
+
+#[tokio::test]
+async fn top_level_commands_are_answered_in_a_new_thread() {
+    let bot = Bot::start(json!([])).await;
+    bot.command("status").await;
+    bot.chat.wait_for_message("I am alive!").await;
+
+    let envelope_id = bot.chat.send_event(json!({
+        "type": "app_mention",
+        "user": "U00000001",
+        "text": format!("<@{}> signup rules frobnicate", BOT_ID),
+        "channel": CHANNEL,
+        "ts": "1540000000.000300",
+    }));
+
+    bot.chat.wait_for_ack(&envelope_id).await;
+    let post = bot.chat.wait_for_post("Could not parse user command").await;
+    assert_eq!(post["thread_ts"], "1540000000.000300");
+}
+
+#[tokio::test]
+async fn edited_and_bot_messages_are_not_commands() {
+    let bot = Bot::start(json!([])).await;
+    bot.command("status").await;
+    bot.chat.wait_for_message("I am alive!").await;
+
+    let edited = bot.chat.send_event(json!({
+        "type": "message",
+        "subtype": "message_changed",
+        "channel": CHANNEL,
+        "message": { "text": format!("<@{}> status", BOT_ID) },
+    }));
+    let from_bot = bot.chat.send_event(json!({
+        "type": "app_mention",
+        "bot_id": "B00000001",
+        "text": format!("<@{}> status", BOT_ID),
+        "channel": CHANNEL,
+    }));
+    bot.chat.wait_for_ack(&edited).await;
+    bot.chat.wait_for_ack(&from_bot).await;
+
+    bot.command("signup rules list").await;
+    bot.chat.wait_for_message("Current rules:").await;
+    let alive = bot
+        .chat
+        .messages()
+        .iter()
+        .filter(|(_, text)| text.starts_with("I am alive!"))
+        .count();
+    assert_eq!(alive, 1);
+}
+
+#[tokio::test]
+async fn socket_mode_reconnects_when_asked() {
+    let bot = Bot::start(json!([])).await;
+    bot.command("status").await;
+    bot.chat.wait_for_message("I am alive!").await;
+
+    bot.chat.request_reconnect();
+    wait_for("the bot to reconnect to Slack", || {
+        if bot.chat.connections() > 1 {
+            Some(())
+        } else {
+            None
+        }
+    })
+    .await;
+
+    bot.command("signup rules list").await;
+    bot.chat.wait_for_message("Current rules:").await;
+}
+
+#[tokio::test]
+async fn match_message_buttons_undo_exempt_and_disable() {
+    let mut delayed = rule(
+        "buttons",
+        json!({ "UsernameContains": "clicky" }),
+        json!(["Close"]),
+    );
+    delayed["delay"] = json!({ "Fixed": 60000 });
+    let bot = Bot::start(json!([delayed])).await;
+
+    bot.signup("Clicky", "clicky@example.com", "10.0.0.8").await;
+    let post = bot.chat.wait_for_post("Rule buttons match").await;
+    let buttons = post["blocks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|block| block["type"] == "actions")
+        .unwrap()["elements"]
+        .clone();
+    let action_ids: Vec<&str> = buttons
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|b| b["action_id"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        action_ids,
+        vec![
+            "undo_actions",
+            "disable_rule",
+            "exempt_user",
+            "false_positive"
+        ]
+    );
+    let value = buttons[0]["value"].as_str().unwrap();
+
+    let click = |action_id: &str, channel: &str| {
+        bot.chat.send_envelope(
+            "interactive",
+            json!({
+                "type": "block_actions",
+                "user": { "id": "U00000001" },
+                "channel": { "id": channel },
+                "actions": [{ "action_id": action_id, "value": value }],
+            }),
+        )
+    };
+
+    let elsewhere = click("undo_actions", "COTHER");
+    bot.chat.wait_for_ack(&elsewhere).await;
+    click("undo_actions", CHANNEL);
+    bot.chat
+        .wait_for_message("Cancelled 1 pending actions and reverting 0 sent actions")
+        .await;
+
+    click("exempt_user", CHANNEL);
+    bot.chat.wait_for_message("Exemption added!").await;
+    click("disable_rule", CHANNEL);
+    bot.chat.wait_for_message("1 rules disabled.").await;
+    click("false_positive", CHANNEL);
+    bot.chat
+        .wait_for_message("by rule buttons as a false positive")
+        .await;
+
+    bot.command("signup exemptions list").await;
+    let (_, exemptions) = bot.chat.wait_for_message("Current exemptions").await;
+    assert!(exemptions.contains("user-clicky"));
+    assert_eq!(bot.lichess.actions().len(), 0);
+    assert!(bot
+        .chat
+        .messages()
+        .iter()
+        .all(|(_, text)| !text.starts_with("Nothing to revert")));
+}
+
+#[tokio::test]
+async fn false_positives_lower_precision_in_stats() {
+    let mut noisy = rule(
+        "noisy",
+        json!({ "UsernameContains": "noisy" }),
+        json!(["Shadowban"]),
+    );
+    noisy["match_count"] = json!(2);
+    noisy["most_recent_caught"] = json!(["Noisy1", "Noisy2"]);
+    let bot = Bot::start(json!([
+        noisy,
+        rule(
+            "quiet",
+            json!({ "IpMatch": "10.9.9.9" }),
+            json!(["Shadowban"])
+        ),
+    ]))
+    .await;
+
+    bot.command("signup rules false-positive noisy Noisy1")
+        .await;
+    let (_, marked) = bot
+        .chat
+        .wait_for_message("by rule noisy as a false positive")
+        .await;
+    assert!(marked.contains("Precision: 50%. This is below 80%"));
+
+    bot.command("signup rules false-positive noisy noisy1")
+        .await;
+    bot.chat
+        .wait_for_message("is already marked as a false positive of rule noisy")
+        .await;
+
+    bot.command("signup rules stats").await;
+    let (_, stats) = bot.chat.wait_for_message("per day").await;
+    let noisy_line = stats.lines().find(|l| l.starts_with("noisy")).unwrap();
+    assert!(noisy_line.contains("50%"));
+    assert!(noisy_line.ends_with("!"));
+    let quiet_line = stats.lines().find(|l| l.starts_with("quiet")).unwrap();
+    assert!(quiet_line.contains("n/a"));
+    assert!(stats.ends_with("! Precision below 80%."));
+
+    bot.command("signup rules show noisy").await;
+    bot.chat
+        .wait_for_message("Precision: 50% (1 false positives of 2 matches)")
+        .await;
+}
+
+#[tokio::test]
+async fn only_caught_users_are_marked_as_false_positives() {
+    let bot = Bot::start(json!([rule(
+        "dragnet",
+        json!({ "UsernameContains": "dragged" }),
+        json!(["Shadowban"]),
+    )]))
+    .await;
+
+    // More than the rule's recent matches, so the first one is only in the audit trail.
+    for i in 1..6 {
+        bot.signup(&format!("Dragged{}", i), "dragged@example.com", "10.0.3.1")
+            .await;
+    }
+    bot.lichess.wait_for_actions(5).await;
+    bot.command("signup rules show dragnet").await;
+    bot.chat.wait_for_message("5 matches. Recent matches").await;
+
+    bot.command("signup rules false-positive dragnet Bystander")
+        .await;
+    bot.chat
+        .wait_for_message("was not caught by rule dragnet, not marking it")
+        .await;
+
+    for _ in 0..2 {
+        bot.command("signup rules false-positive dragnet dragged1")
+            .await;
+    }
+    bot.chat
+        .wait_for_message("by rule dragnet as a false positive. Precision: 80%.")
+        .await;
+    bot.chat
+        .wait_for_message("is already marked as a false positive of rule dragnet")
+        .await;
+
+    bot.command("signup rules show dragnet").await;
+    bot.chat
+        .wait_for_message("Precision: 80% (1 false positives of 5 matches)")
+        .await;
+}
+
+#[tokio::test]
+async fn rules_are_shown_in_full_exported_and_imported() {
+    let mut first = rule(
+        "first",
+        json!({ "IpMatch": "10.0.0.1" }),
+        json!(["Shadowban"]),
+    );
+    first["match_count"] = json!(2);
+    first["most_recent_caught"] = json!(["Caught1", "Caught2"]);
+    first["susp_ip"] = json!(true);
+    let bot = Bot::start(json!([
+        first,
+        rule("second", json!({ "IpMatch": "10.0.0.2" }), json!(["Close"])),
+    ]))
+    .await;
+
+    bot.command("signup rules show first").await;
+    let (_, shown) = bot
+        .chat
+        .wait_for_message("Rule first is enabled, for suspicious IPs only. Weight: +0.")
+        .await;
+    assert!(shown.contains("2 matches. Recent matches: <"));
+
+    bot.command("signup rules export ^first$").await;
+    let file = bot.chat.wait_for_file("\"name\": \"first\"").await;
+    assert!(file.filename.starts_with("rules-"));
+    assert_eq!(file.channel.as_deref(), Some(CHANNEL));
+    assert!(!file.content.contains("second"));
+
+    let import = format!("signup rules import ```json\n{}\n```", file.content);
+    bot.command(&import).await;
+    bot.chat
+        .wait_for_message("Import preview. Skipped (name taken): first.")
+        .await;
+
+    bot.command(&import.replace("import", "import --apply --rename"))
+        .await;
+    bot.chat
+        .wait_for_message("Rules imported. Renamed: first to first-imported.")
+        .await;
+    bot.command("signup rules show first-imported").await;
+    bot.chat
+        .wait_for_message("Rule first-imported is enabled, for suspicious IPs only")
+        .await;
+    bot.chat
+        .wait_for_message("0 matches. Recent matches: none")
+        .await;
+
+    bot.command("signup rules import ```{\"name\": \"broken\"}```")
+        .await;
+    bot.chat.wait_for_post("Invalid rules JSON").await;
+}
+
+#[tokio::test]
+async fn rules_are_edited_in_place() {
+    let mut first = rule(
+        "first",
+        json!({ "IpMatch": "10.0.0.1" }),
+        json!(["Shadowban"]),
+    );
+    first["match_count"] = json!(4);
+    first["most_recent_caught"] = json!(["Caught1"]);
+    let bot = Bot::start(json!([first])).await;
+
+    bot.command("signup rules edit first set actions close+shadowban")
+        .await;
+    bot.chat
+        .wait_for_message(
+            "Rule first edited: actions changed from [Shadowban] to [Close, Shadowban].",
+        )
+        .await;
+    bot.command("signup rules edit first set criterion username contains edited")
+        .await;
+    bot.chat
+        .wait_for_message("to Username contains (case-insensitive) `edited`")
+        .await;
+    bot.command("signup rules edit first set actions none")
+        .await;
+    bot.chat
+        .wait_for_message("Error on editing rule: Rule first has no actions and no weight")
+        .await;
+    bot.command("signup rules edit first rename renamed").await;
+    bot.chat
+        .wait_for_message("Rule first edited: renamed from first to renamed.")
+        .await;
+
+    bot.command("signup rules show renamed").await;
+    let (_, shown) = bot.chat.wait_for_message("Rule renamed is enabled").await;
+    assert!(shown.contains("4 matches."));
+    assert!(shown.contains("renamed from first to renamed"));
+
+    bot.signup("EditedUser", "edited@example.com", "10.0.0.5")
+        .await;
+    let mut paths: Vec<String> = bot
+        .lichess
+        .wait_for_actions(2)
+        .await
+        .into_iter()
+        .map(|a| a.path)
+        .collect();
+    paths.sort();
+    assert_eq!(
+        paths,
+        vec!["/mod/EditedUser/close", "/mod/EditedUser/troll/true"]
+    );
+}
+
+#[tokio::test]
+async fn commands_during_shutdown_are_answered() {
+    let mut bot = Bot::start(json!([])).await;
+    assert!(bot.shutdown().await);
+
+    bot.command("signup rules list").await;
+    bot.chat.wait_for_message("The bot is shutting down.").await;
+}
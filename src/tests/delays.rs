@@ -0,0 +1,112 @@
+use crate::signup::rules::{Action, Delay, DelayDefaults, Rule, MAX_DELAY_MS};
+use crate::tests::{rule, Bot};
+
+const DEFAULTS: DelayDefaults = DelayDefaults {
+    default: Delay::Fixed(10000),
+    actions: &[("close", Delay::Fixed(20000))],
+};
+
+fn parse_rule(json: serde_json::Value) -> Rule {
+    serde_json::from_value(json).unwrap()
+}
+
+#[test]
+fn millis_scale_random_delays_by_the_sample() {
+    assert_eq!(Delay::Immediate.millis(0.7), 0);
+    assert_eq!(Delay::Fixed(1500).millis(0.7), 1500);
+    assert_eq!(Delay::Random(1000, 3000).millis(0.0), 1000);
+    assert_eq!(Delay::Random(1000, 3000).millis(0.5), 2000);
+    assert!(Delay::Random(1000, 3000).millis(0.999_999) < 3000);
+}
+
+#[test]
+fn delays_drawn_together_keep_their_order() {
+    let engine = Delay::Random(30000, 180000);
+    let close = Delay::Random(31500, 181500);
+    for i in 0..100 {
+        let sample = i as f64 / 100.0;
+        assert_eq!(close.millis(sample) - engine.millis(sample), 1500);
+    }
+}
+
+#[test]
+fn per_action_delays_win_over_rule_delay_nodelay_and_defaults() {
+    let mut json = rule(
+        "r",
+        json!({ "IpMatch": "10.0.0.1" }),
+        json!(["EngineMark", "Close"]),
+    );
+    assert_eq!(
+        parse_rule(json.clone()).delay_for(&Action::EngineMark, &DEFAULTS),
+        Delay::Fixed(10000)
+    );
+    assert_eq!(
+        parse_rule(json.clone()).delay_for(&Action::Close, &DEFAULTS),
+        Delay::Fixed(20000)
+    );
+
+    json["no_delay"] = json!(true);
+    assert_eq!(
+        parse_rule(json.clone()).delay_for(&Action::Close, &DEFAULTS),
+        Delay::Immediate
+    );
+
+    json["delay"] = json!({ "Fixed": 5000 });
+    assert_eq!(
+        parse_rule(json.clone()).delay_for(&Action::Close, &DEFAULTS),
+        Delay::Fixed(5000)
+    );
+
+    json["action_delays"] = json!([["close", { "Random": [1000, 2000] }]]);
+    let rule = parse_rule(json);
+    assert_eq!(
+        rule.delay_for(&Action::Close, &DEFAULTS),
+        Delay::Random(1000, 2000)
+    );
+    assert_eq!(
+        rule.delay_for(&Action::EngineMark, &DEFAULTS),
+        Delay::Fixed(5000)
+    );
+}
+
+#[test]
+fn inverted_and_overlong_delays_are_invalid() {
+    assert!(Delay::Random(2000, 1000).validate().is_err());
+    assert!(Delay::Fixed(MAX_DELAY_MS + 1).validate().is_err());
+    assert!(Delay::Random(0, MAX_DELAY_MS).validate().is_ok());
+
+    let mut json = rule("r", json!({ "IpMatch": "10.0.0.1" }), json!(["Close"]));
+    json["action_delays"] = json!([["close", { "Random": [5000, 1000] }]]);
+    assert!(parse_rule(json).validate().is_err());
+}
+
+#[tokio::test]
+async fn actions_of_a_signup_share_their_random_draw() {
+    let mut raid = rule(
+        "raid",
+        json!({ "UsernameContains": "drawn" }),
+        json!(["Close", "EngineMark"]),
+    );
+    raid["action_delays"] = json!([
+        ["engine", { "Random": [0, 1000] }],
+        ["close", { "Random": [100, 1100] }],
+    ]);
+    let bot = Bot::start(json!([raid])).await;
+
+    for i in 1..5 {
+        bot.signup(&format!("Drawn{}", i), "drawn@example.com", "10.0.0.1")
+            .await;
+    }
+
+    let actions = bot.lichess.wait_for_actions(8).await;
+    let position = |path: String| actions.iter().position(|a| a.path == path).unwrap();
+    for i in 1..5 {
+        let engine = position(format!("/mod/Drawn{}/engine/true", i));
+        let close = position(format!("/mod/Drawn{}/close", i));
+        assert!(
+            engine < close,
+            "Drawn{} was closed before its engine mark",
+            i
+        );
+    }
+}
@@ -0,0 +1,823 @@
+use crate::chat::ChatConfig;
+use crate::slack::{SlackConfig, SlackConnection};
+use crate::tests::{leak, wait_for, BOT_ID};
+use crate::zulip::ZulipConfig;
+use bytes::Bytes;
+use futures_util::{stream, SinkExt, StreamExt};
+use http_body_util::combinators::UnsyncBoxBody;
+use http_body_util::{BodyExt, Full, StreamBody};
+use hyper::body::{Frame, Incoming};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{HeaderMap, Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio_tungstenite::accept_async;
+use tokio_tungstenite::tungstenite::Message;
+use url::form_urlencoded;
+
+type MockBody = UnsyncBoxBody<Bytes, Infallible>;
+
+#[derive(Clone, Debug)]
+pub struct RecordedRequest {
+    pub method: Method,
+    pub path: String,
+    pub authorization: Option<String>,
+    pub headers: HeaderMap,
+    pub body: String,
+}
+
+fn full(body: String) -> Response<MockBody> {
+    Response::new(Full::new(Bytes::from(body)).boxed_unsync())
+}
+
+/// Serves HTTP on a free local port and returns its base URL.
+async fn serve<F>(handle: F) -> String
+where
+    F: Fn(RecordedRequest) -> Response<MockBody> + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handle = Arc::new(handle);
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(_) => continue,
+            };
+            let handle = handle.clone();
+            tokio::spawn(async move {
+                let service = service_fn(move |req: Request<Incoming>| {
+                    let handle = handle.clone();
+                    async move {
+                        let method = req.method().clone();
+                        let path = req
+                            .uri()
+                            .path_and_query()
+                            .map(|p| p.as_str().to_owned())
+                            .unwrap_or_default();
+                        let authorization = req
+                            .headers()
+                            .get(hyper::header::AUTHORIZATION)
+                            .and_then(|v| v.to_str().ok())
+                            .map(|v| v.to_owned());
+                        let headers = req.headers().clone();
+                        let body = req.into_body().collect().await?.to_bytes();
+                        Ok::<_, hyper::Error>(handle(RecordedRequest {
+                            method,
+                            path,
+                            authorization,
+                            headers,
+                            body: String::from_utf8_lossy(&body).into_owned(),
+                        }))
+                    }
+                });
+                let _ = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await;
+            });
+        }
+    });
+
+    url
+}
+
+/// Serves `/api/stream/mod` as an NDJSON stream fed by `push_event`, and records every other
+/// request as a mod action.
+pub struct MockLichess {
+    pub url: String,
+    /// One per event stream request, events go to all that are still open.
+    streams: Arc<Mutex<Vec<UnboundedSender<String>>>>,
+    connections: Arc<Mutex<usize>>,
+    actions: Arc<Mutex<Vec<RecordedRequest>>>,
+    failing: Arc<AtomicBool>,
+}
+
+impl MockLichess {
+    pub async fn start() -> MockLichess {
+        let streams: Arc<Mutex<Vec<UnboundedSender<String>>>> = Arc::new(Mutex::new(vec![]));
+        let connections = Arc::new(Mutex::new(0));
+        let actions = Arc::new(Mutex::new(vec![]));
+        let failing = Arc::new(AtomicBool::new(false));
+
+        let (streams2, connections2, actions2, failing2) = (
+            streams.clone(),
+            connections.clone(),
+            actions.clone(),
+            failing.clone(),
+        );
+        let url = serve(move |req| {
+            if req.path == "/api/stream/mod" {
+                let (tx, rx) = unbounded_channel::<String>();
+                streams2.lock().unwrap().push(tx);
+                *connections2.lock().unwrap() += 1;
+                let lines = stream::unfold(rx, |mut rx| async move {
+                    let line = rx.recv().await?;
+                    Some((Ok(Frame::data(Bytes::from(line))), rx))
+                });
+                Response::new(StreamBody::new(lines).boxed_unsync())
+            } else {
+                actions2.lock().unwrap().push(req);
+                if failing2.load(Ordering::SeqCst) {
+                    let mut res = full("{\"error\":\"failing\"}".to_owned());
+                    *res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                    res
+                } else {
+                    full("{\"ok\":true}".to_owned())
+                }
+            }
+        })
+        .await;
+
+        MockLichess {
+            url,
+            streams,
+            connections,
+            actions,
+            failing,
+        }
+    }
+
+    /// Sends one line on the event stream, waiting for the bot to be connected.
+    pub async fn push_event(&self, json: String) {
+        self.push_chunk(json + "\n").await;
+    }
+
+    /// Sends `chunk` as is on every open event stream, waiting for the bot to be connected.
+    pub async fn push_chunk(&self, chunk: String) {
+        wait_for("the bot to connect to the event stream", || {
+            let mut streams = self.streams.lock().unwrap();
+            streams.retain(|tx| !tx.is_closed());
+            let sent = streams
+                .iter()
+                .filter(|tx| tx.send(chunk.clone()).is_ok())
+                .count();
+            if sent > 0 {
+                Some(())
+            } else {
+                None
+            }
+        })
+        .await;
+    }
+
+    /// Ends the event stream responses, as lichess does on a deploy.
+    pub fn disconnect(&self) {
+        self.streams.lock().unwrap().clear();
+    }
+
+    /// How many event stream requests there were.
+    pub fn connections(&self) -> usize {
+        *self.connections.lock().unwrap()
+    }
+
+    /// How many event stream responses the bot is still reading.
+    pub fn live_connections(&self) -> usize {
+        self.streams
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|tx| !tx.is_closed())
+            .count()
+    }
+
+    /// Makes mod actions fail with status 500, or succeed again.
+    pub fn fail_actions(&self, failing: bool) {
+        self.failing.store(failing, Ordering::SeqCst);
+    }
+
+    pub fn actions(&self) -> Vec<RecordedRequest> {
+        self.actions.lock().unwrap().clone()
+    }
+
+    pub async fn wait_for_actions(&self, count: usize) -> Vec<RecordedRequest> {
+        wait_for(&format!("{} mod actions", count), || {
+            let actions = self.actions();
+            if actions.len() >= count {
+                Some(actions)
+            } else {
+                None
+            }
+        })
+        .await
+    }
+}
+
+/// A chat platform the bot can connect to.
+pub trait MockChat {
+    fn config(&self) -> ChatConfig;
+
+    fn connections(&self) -> usize;
+
+    /// Sends `command` to the bot in `channel`, mentioning it first.
+    fn send_command(&self, command: &str, channel: &str);
+}
+
+enum SocketCommand {
+    Send(String),
+    Disconnect,
+}
+
+/// The app-level token the mock accepts for Socket Mode.
+pub const APP_TOKEN: &str = "test-app-token";
+
+/// A file uploaded to Slack. The channel is set once the upload is completed.
+#[derive(Clone, Debug, Default)]
+pub struct SlackFile {
+    pub id: String,
+    pub filename: String,
+    pub content: String,
+    pub channel: Option<String>,
+}
+
+/// Serves `apps.connections.open` (Socket Mode) or `rtm.connect` with a local WebSocket, and
+/// records `chat.postMessage` calls and file uploads.
+pub struct MockSlack {
+    pub api_url: String,
+    socket_mode: bool,
+    socket: UnboundedSender<SocketCommand>,
+    connections: Arc<Mutex<usize>>,
+    envelopes: Mutex<u64>,
+    posts: Arc<Mutex<Vec<serde_json::Value>>>,
+    /// Whether `chat.postMessage` answers `"ok": false`, as when the bot left the channel.
+    failing_posts: Arc<AtomicBool>,
+    replies: Arc<Mutex<Vec<String>>>,
+    files: Arc<Mutex<Vec<SlackFile>>>,
+}
+
+impl MockSlack {
+    pub async fn start() -> MockSlack {
+        MockSlack::start_with(true).await
+    }
+
+    pub async fn start_rtm() -> MockSlack {
+        MockSlack::start_with(false).await
+    }
+
+    async fn start_with(socket_mode: bool) -> MockSlack {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let ws_url = format!("ws://{}", listener.local_addr().unwrap());
+        let (socket_tx, socket_rx) = unbounded_channel();
+        let connections = Arc::new(Mutex::new(0));
+        let replies = Arc::new(Mutex::new(vec![]));
+        tokio::spawn(serve_socket(
+            listener,
+            socket_mode,
+            socket_rx,
+            connections.clone(),
+            replies.clone(),
+        ));
+
+        let files: Arc<Mutex<Vec<SlackFile>>> = Arc::new(Mutex::new(vec![]));
+        let files2 = files.clone();
+        let upload_url = serve(move |req| {
+            let id = req.path.trim_start_matches('/');
+            if let Some(file) = files2.lock().unwrap().iter_mut().find(|f| f.id == id) {
+                file.content = req.body;
+            }
+            full("OK".to_owned())
+        })
+        .await;
+
+        let posts = Arc::new(Mutex::new(vec![]));
+        let failing_posts = Arc::new(AtomicBool::new(false));
+        let (posts2, failing_posts2) = (posts.clone(), failing_posts.clone());
+        let files2 = files.clone();
+        let api_url = serve(move |req| {
+            if req.path.starts_with("/api/rtm.connect") {
+                full(json!({ "ok": true, "url": &ws_url }).to_string())
+            } else if req.path == "/api/apps.connections.open" {
+                if req.authorization == Some(format!("Bearer {}", APP_TOKEN)) {
+                    full(json!({ "ok": true, "url": &ws_url }).to_string())
+                } else {
+                    full(json!({ "ok": false, "error": "invalid_auth" }).to_string())
+                }
+            } else if req.path == "/api/files.getUploadURLExternal" {
+                let mut files = files2.lock().unwrap();
+                let id = format!("F{}", files.len() + 1);
+                let filename = form_urlencoded::parse(req.body.as_bytes())
+                    .find(|(k, _)| k == "filename")
+                    .map(|(_, v)| v.into_owned())
+                    .unwrap_or_default();
+                files.push(SlackFile {
+                    id: id.clone(),
+                    filename,
+                    ..Default::default()
+                });
+                full(
+                    json!({
+                        "ok": true,
+                        "upload_url": format!("{}/{}", upload_url, id),
+                        "file_id": id,
+                    })
+                    .to_string(),
+                )
+            } else if req.path == "/api/files.completeUploadExternal" {
+                let body: serde_json::Value = serde_json::from_str(&req.body).unwrap();
+                let id = body["files"][0]["id"].as_str().unwrap_or("");
+                if let Some(file) = files2.lock().unwrap().iter_mut().find(|f| f.id == id) {
+                    file.channel = body["channel_id"].as_str().map(|c| c.to_owned());
+                }
+                full("{\"ok\":true}".to_owned())
+            } else if req.path == "/api/chat.postMessage" {
+                posts2
+                    .lock()
+                    .unwrap()
+                    .push(serde_json::from_str(&req.body).unwrap());
+                if failing_posts2.load(Ordering::SeqCst) {
+                    full(json!({ "ok": false, "error": "not_in_channel" }).to_string())
+                } else {
+                    full("{\"ok\":true}".to_owned())
+                }
+            } else {
+                full("{\"ok\":true}".to_owned())
+            }
+        })
+        .await
+            + "/api";
+
+        MockSlack {
+            api_url,
+            socket_mode,
+            socket: socket_tx,
+            connections,
+            envelopes: Mutex::new(0),
+            posts,
+            failing_posts,
+            replies,
+            files,
+        }
+    }
+
+    /// Sends a message to the bot, as if a user typed it in `channel`. In Socket Mode, a
+    /// message that mentions someone is also sent as an `app_mention` event.
+    pub fn send_message(&self, text: &str, channel: &str) {
+        let message = json!({
+            "type": "message",
+            "user": "U00000001",
+            "text": text,
+            "client_msg_id": "00000000-0000-0000-0000-000000000000",
+            "team": "T00000001",
+            "channel": channel,
+            "event_ts": "1540000000.000100",
+            "ts": "1540000000.000100",
+        });
+        if !self.socket_mode {
+            let _ = self.socket.send(SocketCommand::Send(message.to_string()));
+            return;
+        }
+
+        self.send_event(message.clone());
+        if text.starts_with("<@") {
+            let mut mention = message;
+            mention["type"] = json!("app_mention");
+            self.send_event(mention);
+        }
+    }
+
+    /// Sends a Socket Mode `events_api` envelope with `event`, returning its envelope ID.
+    pub fn send_event(&self, event: serde_json::Value) -> String {
+        self.send_envelope(
+            "events_api",
+            json!({ "type": "event_callback", "event": event }),
+        )
+    }
+
+    pub fn send_envelope(&self, type_: &str, payload: serde_json::Value) -> String {
+        let mut envelopes = self.envelopes.lock().unwrap();
+        *envelopes += 1;
+        let envelope_id = format!("envelope-{}", envelopes);
+        let envelope = json!({
+            "envelope_id": &envelope_id,
+            "type": type_,
+            "payload": payload,
+            "accepts_response_payload": false,
+        });
+        let _ = self.socket.send(SocketCommand::Send(envelope.to_string()));
+        envelope_id
+    }
+
+    /// Asks the bot to reconnect, as Slack does every few hours in Socket Mode.
+    pub fn request_reconnect(&self) {
+        let message = json!({ "type": "disconnect", "reason": "refresh_requested" });
+        let _ = self.socket.send(SocketCommand::Send(message.to_string()));
+    }
+
+    pub fn disconnect(&self) {
+        let _ = self.socket.send(SocketCommand::Disconnect);
+    }
+
+    /// Makes `chat.postMessage` fail with `not_in_channel`, or succeed again.
+    pub fn fail_posts(&self, failing: bool) {
+        self.failing_posts.store(failing, Ordering::SeqCst);
+    }
+
+    /// Bodies of the `chat.postMessage` calls.
+    pub fn posts(&self) -> Vec<serde_json::Value> {
+        self.posts.lock().unwrap().clone()
+    }
+
+    /// Messages posted through `chat.postMessage`, as `(channel, text)`.
+    pub fn messages(&self) -> Vec<(String, String)> {
+        self.posts()
+            .iter()
+            .map(|post| {
+                (
+                    post["channel"].as_str().unwrap_or("").to_owned(),
+                    post["text"].as_str().unwrap_or("").to_owned(),
+                )
+            })
+            .collect()
+    }
+
+    pub async fn wait_for_post(&self, needle: &str) -> serde_json::Value {
+        wait_for(&format!("a Slack message containing `{}`", needle), || {
+            self.posts()
+                .into_iter()
+                .find(|post| post["text"].as_str().unwrap_or("").contains(needle))
+        })
+        .await
+    }
+
+    pub async fn wait_for_message(&self, needle: &str) -> (String, String) {
+        let post = self.wait_for_post(needle).await;
+        (
+            post["channel"].as_str().unwrap_or("").to_owned(),
+            post["text"].as_str().unwrap_or("").to_owned(),
+        )
+    }
+
+    /// Waits for a completed file upload whose content contains `needle`.
+    pub async fn wait_for_file(&self, needle: &str) -> SlackFile {
+        wait_for(&format!("a Slack file containing `{}`", needle), || {
+            self.files
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|f| f.channel.is_some() && f.content.contains(needle))
+                .cloned()
+        })
+        .await
+    }
+
+    /// Text of the messages the bot sent back over RTM.
+    pub async fn wait_for_reply(&self, needle: &str) -> String {
+        wait_for(&format!("an RTM reply containing `{}`", needle), || {
+            self.replies
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|reply| reply.contains(needle))
+                .cloned()
+        })
+        .await
+    }
+
+    /// Waits for the bot to acknowledge a Socket Mode envelope.
+    pub async fn wait_for_ack(&self, envelope_id: &str) {
+        let ack = json!({ "envelope_id": envelope_id }).to_string();
+        wait_for(&format!("an ack of `{}`", envelope_id), || {
+            self.replies
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|reply| **reply == ack)
+                .map(|_| ())
+        })
+        .await
+    }
+}
+
+impl MockChat for MockSlack {
+    fn config(&self) -> ChatConfig {
+        ChatConfig::Slack(SlackConfig {
+            api_url: leak(self.api_url.clone()),
+            bot_token: "test-slack-token",
+            bot_user_id: BOT_ID,
+            connection: if self.socket_mode {
+                SlackConnection::SocketMode {
+                    app_token: APP_TOKEN,
+                }
+            } else {
+                SlackConnection::Rtm
+            },
+        })
+    }
+
+    fn connections(&self) -> usize {
+        *self.connections.lock().unwrap()
+    }
+
+    fn send_command(&self, command: &str, channel: &str) {
+        self.send_message(&format!("<@{}> {}", BOT_ID, command), channel);
+    }
+}
+
+async fn serve_socket(
+    listener: TcpListener,
+    socket_mode: bool,
+    mut commands: UnboundedReceiver<SocketCommand>,
+    connections: Arc<Mutex<usize>>,
+    replies: Arc<Mutex<Vec<String>>>,
+) {
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(_) => continue,
+        };
+        let mut socket = match accept_async(stream).await {
+            Ok(socket) => socket,
+            Err(_) => continue,
+        };
+        *connections.lock().unwrap() += 1;
+        if socket_mode {
+            let hello = json!({ "type": "hello", "num_connections": 1 });
+            if socket
+                .send(Message::Text(hello.to_string().into()))
+                .await
+                .is_err()
+            {
+                continue;
+            }
+        }
+
+        loop {
+            tokio::select! {
+                command = commands.recv() => match command {
+                    Some(SocketCommand::Send(text)) => {
+                        if socket.send(Message::Text(text.into())).await.is_err() {
+                            break;
+                        }
+                    }
+                    Some(SocketCommand::Disconnect) | None => break,
+                },
+                message = socket.next() => match message {
+                    Some(Ok(Message::Text(text))) => replies.lock().unwrap().push(text.to_string()),
+                    Some(Ok(_)) => {}
+                    _ => break,
+                },
+            }
+        }
+    }
+}
+
+pub const ZULIP_BOT_EMAIL: &str = "bot@zulip.example.com";
+pub const ZULIP_BOT_NAME: &str = "Event Bot";
+const ZULIP_API_KEY: &str = "test-api-key";
+
+/// The event queue: events wait here until the bot polls for them.
+#[derive(Default)]
+struct ZulipQueue {
+    next_id: i64,
+    events: Vec<serde_json::Value>,
+    poll: Option<UnboundedSender<String>>,
+}
+
+fn zulip_events(events: Vec<serde_json::Value>) -> String {
+    json!({ "result": "success", "msg": "", "events": events }).to_string()
+}
+
+/// Serves the Zulip event queue and records sent messages.
+pub struct MockZulip {
+    pub site: String,
+    queue: Arc<Mutex<ZulipQueue>>,
+    connections: Arc<Mutex<usize>>,
+    messages: Arc<Mutex<Vec<(String, String, String)>>>,
+}
+
+impl MockZulip {
+    pub async fn start() -> MockZulip {
+        let queue: Arc<Mutex<ZulipQueue>> = Arc::new(Mutex::new(Default::default()));
+        let connections = Arc::new(Mutex::new(0));
+        let messages = Arc::new(Mutex::new(vec![]));
+
+        let (queue2, connections2, messages2) =
+            (queue.clone(), connections.clone(), messages.clone());
+        let authorization = format!(
+            "Basic {}",
+            base64::encode(format!("{}:{}", ZULIP_BOT_EMAIL, ZULIP_API_KEY))
+        );
+        let site = serve(move |req| {
+            if req.authorization.as_ref() != Some(&authorization) {
+                return full(json!({ "result": "error", "msg": "Unauthorized" }).to_string());
+            }
+            let path = req.path.split('?').next().unwrap_or("");
+            match path {
+                "/api/v1/register" => {
+                    *connections2.lock().unwrap() += 1;
+                    full(
+                        json!({ "result": "success", "queue_id": "queue", "last_event_id": -1 })
+                            .to_string(),
+                    )
+                }
+                "/api/v1/events" => {
+                    let mut queue = queue2.lock().unwrap();
+                    if !queue.events.is_empty() {
+                        return full(zulip_events(queue.events.drain(..).collect()));
+                    }
+                    let (tx, rx) = unbounded_channel::<String>();
+                    queue.poll = Some(tx);
+                    let body = stream::unfold(Some(rx), |rx| async move {
+                        let body = rx?.recv().await?;
+                        Some((Ok(Frame::data(Bytes::from(body))), None))
+                    });
+                    Response::new(StreamBody::new(body).boxed_unsync())
+                }
+                "/api/v1/messages" => {
+                    let form: Vec<(String, String)> = form_urlencoded::parse(req.body.as_bytes())
+                        .into_owned()
+                        .collect();
+                    let field = |name: &str| {
+                        form.iter()
+                            .find(|(k, _)| k == name)
+                            .map(|(_, v)| v.clone())
+                            .unwrap_or_default()
+                    };
+                    messages2
+                        .lock()
+                        .unwrap()
+                        .push((field("to"), field("topic"), field("content")));
+                    full(json!({ "result": "success", "id": 1 }).to_string())
+                }
+                _ => full(json!({ "result": "error", "msg": "Not found" }).to_string()),
+            }
+        })
+        .await;
+
+        MockZulip {
+            site,
+            queue,
+            connections,
+            messages,
+        }
+    }
+
+    /// Sends a stream message, as if a user wrote it in `topic` of `stream`.
+    pub fn send_message(&self, content: &str, stream: &str, topic: &str) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.next_id += 1;
+        let event = json!({
+            "type": "message",
+            "id": queue.next_id,
+            "message": {
+                "type": "stream",
+                "display_recipient": stream,
+                "subject": topic,
+                "content": content,
+                "sender_email": "mod@zulip.example.com",
+                "sender_full_name": "A Mod",
+            },
+        });
+        let sent = match queue.poll.take() {
+            Some(poll) => poll.send(zulip_events(vec![event.clone()])).is_ok(),
+            None => false,
+        };
+        if !sent {
+            queue.events.push(event);
+        }
+    }
+
+    /// Messages the bot sent, as `(stream, topic, content)`.
+    pub fn messages(&self) -> Vec<(String, String, String)> {
+        self.messages.lock().unwrap().clone()
+    }
+
+    pub async fn wait_for_message(&self, needle: &str) -> (String, String, String) {
+        wait_for(&format!("a Zulip message containing `{}`", needle), || {
+            self.messages()
+                .into_iter()
+                .find(|(_, _, content)| content.contains(needle))
+        })
+        .await
+    }
+}
+
+impl MockChat for MockZulip {
+    fn config(&self) -> ChatConfig {
+        ChatConfig::Zulip(ZulipConfig {
+            site: leak(self.site.clone()),
+            bot_email: ZULIP_BOT_EMAIL,
+            api_key: ZULIP_API_KEY,
+            bot_name: ZULIP_BOT_NAME,
+        })
+    }
+
+    fn connections(&self) -> usize {
+        *self.connections.lock().unwrap()
+    }
+
+    fn send_command(&self, command: &str, channel: &str) {
+        self.send_message(
+            &format!("@**{}** {}", ZULIP_BOT_NAME, command),
+            channel,
+            "commands",
+        );
+    }
+}
+
+/// Records the embeds posted to a Discord webhook.
+pub struct MockDiscord {
+    pub url: String,
+    embeds: Arc<Mutex<Vec<serde_json::Value>>>,
+}
+
+impl MockDiscord {
+    pub async fn start() -> MockDiscord {
+        let embeds = Arc::new(Mutex::new(vec![]));
+        let embeds2 = embeds.clone();
+        let url = serve(move |req| {
+            let body: serde_json::Value = serde_json::from_str(&req.body).unwrap();
+            if let Some(posted) = body["embeds"].as_array() {
+                embeds2.lock().unwrap().extend(posted.iter().cloned());
+            }
+            full("".to_owned())
+        })
+        .await
+            + "/api/webhooks/1/token";
+
+        MockDiscord { url, embeds }
+    }
+
+    pub fn embeds(&self) -> Vec<serde_json::Value> {
+        self.embeds.lock().unwrap().clone()
+    }
+
+    /// Waits for an embed whose title or description contains `needle`.
+    pub async fn wait_for_embed(&self, needle: &str) -> serde_json::Value {
+        wait_for(&format!("a Discord embed with `{}`", needle), || {
+            self.embeds().into_iter().find(|embed| {
+                embed["title"].as_str().unwrap_or("").contains(needle)
+                    || embed["description"].as_str().unwrap_or("").contains(needle)
+            })
+        })
+        .await
+    }
+}
+
+/// A generic webhook, that can be made to fail.
+pub struct MockWebhook {
+    pub url: String,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+    failures: Arc<AtomicUsize>,
+}
+
+impl MockWebhook {
+    pub async fn start() -> MockWebhook {
+        let requests = Arc::new(Mutex::new(vec![]));
+        let failures = Arc::new(AtomicUsize::new(0));
+        let (requests2, failures2) = (requests.clone(), failures.clone());
+        let url = serve(move |req| {
+            requests2.lock().unwrap().push(req);
+            let failing = failures2
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok();
+            let mut res = full("".to_owned());
+            if failing {
+                *res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            }
+            res
+        })
+        .await
+            + "/hook";
+
+        MockWebhook {
+            url,
+            requests,
+            failures,
+        }
+    }
+
+    /// Makes the next `n` requests fail with a 500.
+    pub fn fail_next(&self, n: usize) {
+        self.failures.store(n, Ordering::SeqCst);
+    }
+
+    /// All delivery attempts, including the failed ones.
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Waits for `n` attempts at delivering a payload of `kind` and returns them.
+    pub async fn wait_for_attempts(&self, kind: &str, n: usize) -> Vec<RecordedRequest> {
+        wait_for(&format!("{} `{}` webhook requests", n, kind), || {
+            let attempts: Vec<_> = self
+                .requests()
+                .into_iter()
+                .filter(|req| {
+                    serde_json::from_str::<serde_json::Value>(&req.body).unwrap()["kind"] == kind
+                })
+                .collect();
+            if attempts.len() >= n {
+                Some(attempts)
+            } else {
+                None
+            }
+        })
+        .await
+    }
+}
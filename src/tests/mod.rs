@@ -0,0 +1,313 @@
+//! End-to-end tests: the bot runs against local mock lichess and Slack servers.
+
+mod admin;
+mod alerts;
+mod backoff;
+mod commands;
+mod delays;
+mod mock;
+mod parser;
+mod signups;
+mod slack;
+mod webhook;
+mod zulip;
+
+use crate::alerts::AlertConfig;
+use crate::backoff::{BackoffPolicy, RetryPolicy};
+use crate::chat::Chat;
+use crate::discord::DiscordWebhookConfig;
+use crate::event::Event;
+use crate::eventhandler::HandlerConfig;
+use crate::signup::rules::{Delay, DelayDefaults, Endpoints, PrecisionWarning};
+use crate::status::StatusConfig;
+use crate::tests::mock::{MockChat, MockDiscord, MockLichess, MockSlack, MockWebhook};
+use crate::webhook::{Delivery, WebhookConfig};
+use crate::{eventhandler, status};
+use rand::{thread_rng, Rng};
+use std::fs;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::task::JoinHandle;
+
+pub const TOKEN: &str = "test-token";
+pub const BOT_ID: &str = "UBOT";
+pub const CHANNEL: &str = "CMODS";
+pub const NOTIFY_CHANNEL: &str = "CNOTIFY";
+pub const WEBHOOK_SECRET: &str = "webhook-secret";
+
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Polls `check` until it returns something, panicking after `TIMEOUT`.
+pub async fn wait_for<T, F: Fn() -> Option<T>>(what: &str, check: F) -> T {
+    let start = Instant::now();
+    loop {
+        if let Some(t) = check() {
+            return t;
+        }
+        if start.elapsed() > TIMEOUT {
+            panic!("Timed out waiting for {}", what);
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+/// Alert thresholds that are never reached.
+pub fn quiet_alerts() -> AlertConfig {
+    AlertConfig {
+        no_signups_for: Duration::from_secs(86400),
+        expected_hours: (0, 24),
+        max_reconnects: usize::MAX,
+        reconnects_window: Duration::from_secs(60),
+        max_action_failure_percent: 100,
+        min_actions: usize::MAX,
+        actions_window: Duration::from_secs(60),
+        max_lua_errors: usize::MAX,
+        lua_errors_window: Duration::from_secs(60),
+    }
+}
+
+pub fn leak(s: String) -> &'static str {
+    Box::leak(s.into_boxed_str())
+}
+
+pub struct Bot<C = MockSlack> {
+    pub lichess: MockLichess,
+    pub chat: C,
+    /// A Discord webhook that gets alerts and the matches of rules named `discord-...`.
+    pub discord: MockDiscord,
+    /// A webhook that gets everything about rules named `hook-...`, in 3 attempts.
+    pub webhook: MockWebhook,
+    pub dead_letter_path: &'static str,
+    pub pending_path: &'static str,
+    pub audit_path: &'static str,
+    /// Serves `/metrics` and `/health` for this bot.
+    pub admin_addr: String,
+    paths: Vec<&'static str>,
+    tx: UnboundedSender<Event>,
+    handler: JoinHandle<bool>,
+}
+
+/// What a test bot starts with: tests set the fields they need on `Options::new`.
+pub struct Options {
+    pub rules: serde_json::Value,
+    pub exemptions: serde_json::Value,
+    /// The pending actions a previous run saved on shutdown.
+    pub pending_actions: serde_json::Value,
+    pub alerts: AlertConfig,
+    /// Action endpoint templates, as `ENDPOINTS` allows.
+    pub overrides: &'static [(&'static str, &'static str)],
+    /// How long the event stream may stay silent before it is restarted.
+    pub stream_timeout: Duration,
+}
+
+impl Options {
+    pub fn new(rules: serde_json::Value) -> Options {
+        Options {
+            rules,
+            exemptions: json!([]),
+            pending_actions: json!([]),
+            alerts: quiet_alerts(),
+            overrides: &[],
+            // As in the default configuration.
+            stream_timeout: Duration::from_secs(90),
+        }
+    }
+
+    /// Starts the bot against new mock lichess and Slack servers.
+    pub async fn start(self) -> Bot {
+        self.start_with(MockLichess::start().await, MockSlack::start().await)
+            .await
+    }
+
+    /// Starts the bot against the given servers, e.g. another chat backend.
+    pub async fn start_with<C: MockChat>(self, lichess: MockLichess, chat: C) -> Bot<C> {
+        let Options {
+            rules,
+            exemptions,
+            pending_actions,
+            alerts,
+            overrides,
+            stream_timeout,
+        } = self;
+        let id: u64 = thread_rng().gen();
+        let dir = std::env::temp_dir();
+        let rules_path = leak(format!("{}/rules-{}.json", dir.display(), id));
+        let exemptions_path = leak(format!("{}/exemptions-{}.json", dir.display(), id));
+        let pending_path = leak(format!("{}/pending-{}.json", dir.display(), id));
+        let audit_path = leak(format!("{}/audit-{}.jsonl", dir.display(), id));
+        let dead_letter_path = leak(format!("{}/dead-letters-{}.jsonl", dir.display(), id));
+        fs::write(rules_path, rules.to_string()).unwrap();
+        fs::write(exemptions_path, exemptions.to_string()).unwrap();
+        fs::write(pending_path, pending_actions.to_string()).unwrap();
+
+        let lichess_url = leak(lichess.url.clone());
+        let discord = MockDiscord::start().await;
+        let webhook = MockWebhook::start().await;
+        let chat_backend = Chat::new(
+            chat.config(),
+            &[DiscordWebhookConfig {
+                url: leak(discord.url.clone()),
+                rules: Some("^discord-"),
+                alerts: true,
+            }],
+            &[WebhookConfig {
+                url: leak(webhook.url.clone()),
+                secret: WEBHOOK_SECRET,
+                rules: Some("^hook-"),
+                kinds: &["match", "action", "error"],
+            }],
+            Delivery {
+                attempts: 3,
+                retry: RetryPolicy {
+                    initial: Duration::from_millis(50),
+                    max: Duration::from_millis(200),
+                    jitter: 0.0,
+                },
+                dead_letter_path,
+            },
+        )
+        .unwrap();
+
+        let (tx, rx) = unbounded_channel();
+        let (status_tx, status_rx) = unbounded_channel();
+
+        status::status_loop(
+            status_rx,
+            tx.clone(),
+            status_tx.clone(),
+            chat_backend.clone(),
+            StatusConfig {
+                lichess_url,
+                token: TOKEN,
+                chat_channel: CHANNEL,
+                notify_channel: NOTIFY_CHANNEL,
+                alerts,
+                backoff: BackoffPolicy {
+                    initial: Duration::from_millis(100),
+                    max: Duration::from_secs(1),
+                    jitter: 0.3,
+                    stable_after: Duration::from_secs(5),
+                },
+                stream_timeout,
+            },
+        );
+        // As often as in `main` relative to the default timeout.
+        status::periodically_ensure_alive_connection(status_tx.clone(), stream_timeout / 6);
+        let admin_addr = crate::admin::serve("127.0.0.1:0", status_tx.clone())
+            .await
+            .unwrap()
+            .to_string();
+        let handler = tokio::spawn(eventhandler::handle_events(
+            rx,
+            status_tx.clone(),
+            chat_backend,
+            HandlerConfig {
+                token: TOKEN,
+                rules_path,
+                exemptions_path,
+                pending_actions_path: pending_path,
+                audit_path,
+                delays: DelayDefaults {
+                    default: Delay::Immediate,
+                    actions: &[],
+                },
+                endpoints: Endpoints {
+                    base_url: lichess_url,
+                    overrides,
+                },
+                precision_warning: PrecisionWarning {
+                    below: 0.8,
+                    min_matches: 2,
+                },
+                chat_channel: CHANNEL,
+                notify_channel: NOTIFY_CHANNEL,
+            },
+        ));
+
+        Bot {
+            lichess,
+            chat,
+            discord,
+            webhook,
+            dead_letter_path,
+            pending_path,
+            audit_path,
+            admin_addr,
+            paths: vec![
+                rules_path,
+                exemptions_path,
+                pending_path,
+                audit_path,
+                dead_letter_path,
+            ],
+            tx,
+            handler,
+        }
+    }
+}
+
+impl Bot {
+    pub async fn start(rules: serde_json::Value) -> Bot {
+        Options::new(rules).start().await
+    }
+}
+
+impl<C: MockChat> Bot<C> {
+    /// Sends a command to the bot the way a moderator would, by mentioning it.
+    pub async fn command(&self, command: &str) {
+        wait_for("the bot to connect to chat", || {
+            if self.chat.connections() > 0 {
+                Some(())
+            } else {
+                None
+            }
+        })
+        .await;
+        self.chat.send_command(command, CHANNEL);
+    }
+
+    pub async fn signup(&self, username: &str, email: &str, ip: &str) {
+        self.lichess
+            .push_event(signup_event(username, email, ip))
+            .await;
+    }
+
+    /// Shuts the event handler down as SIGTERM would, and returns whether it saved everything.
+    pub async fn shutdown(&mut self) -> bool {
+        self.tx.send(Event::InternalShutdown).unwrap();
+        (&mut self.handler).await.unwrap()
+    }
+}
+
+impl<C> Drop for Bot<C> {
+    fn drop(&mut self) {
+        for path in &self.paths {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// A signup on the event stream, as one line of JSON.
+pub fn signup_event(username: &str, email: &str, ip: &str) -> String {
+    json!({
+        "t": "signup",
+        "username": username,
+        "email": email,
+        "ip": ip,
+        "userAgent": "Mozilla/5.0 (X11; Linux x86_64; rv:62.0) Gecko/20100101 Firefox/62.0",
+        "fingerPrint": null,
+    })
+    .to_string()
+}
+
+pub fn rule(
+    name: &str,
+    criterion: serde_json::Value,
+    actions: serde_json::Value,
+) -> serde_json::Value {
+    json!({
+        "name": name,
+        "criterion": criterion,
+        "actions": actions,
+    })
+}
@@ -0,0 +1,110 @@
+use crate::chat::command::{parse_command, UserCommand};
+use crate::event::Event;
+use chrono::{Duration, Utc};
+
+/// The message of the error `command` is rejected with.
+fn error(command: &str) -> String {
+    match parse_command(command) {
+        Ok(_) => panic!("`{}` was accepted", command),
+        Err(err) => err.message,
+    }
+}
+
+/// The event `command` is parsed to.
+fn event(command: &str) -> Event {
+    match parse_command(command) {
+        Ok(UserCommand::Event(event)) => *event,
+        _ => panic!("`{}` is not an event", command),
+    }
+}
+
+#[test]
+fn durations_are_bounded_and_end_with_a_unit() {
+    for command in &[
+        "signup exemptions add e if username contains a for 2€",
+        "signup exemptions add e if username contains a for 9999999999999d",
+        "signup exemptions add e if username contains a for 0d",
+        "signup exemptions add e if username contains a for -1d",
+        "signup revert-rule raiders --since 5é",
+        "signup revert-rule raiders --since 99999999999999w",
+    ] {
+        assert!(error(command).contains("Duration"), "{}", command);
+    }
+}
+
+#[test]
+fn durations_set_exemption_expiry_and_revert_start() {
+    match event("signup exemptions add e if username contains a for 2w") {
+        Event::InternalAddExemption { exemption } => {
+            let expires = exemption.expires.unwrap() - Utc::now();
+            assert!(expires > Duration::days(13) && expires <= Duration::weeks(2));
+        }
+        _ => panic!("expected an exemption"),
+    }
+    match event("signup revert-rule raiders --since 90m") {
+        Event::InternalRevertRule { since, .. } => {
+            let ago = Utc::now() - since.unwrap();
+            assert!(ago >= Duration::minutes(90) && ago < Duration::minutes(91));
+        }
+        _ => panic!("expected a revert"),
+    }
+}
+
+#[test]
+fn inverted_score_ranges_are_rejected() {
+    assert!(
+        error("signup rules add r if score range 5..1 then shadowban")
+            .contains("Score range minimum exceeds its maximum")
+    );
+    assert!(parse_command("signup rules add r if score range 1..5 then shadowban").is_ok());
+}
+
+#[test]
+fn delays_are_finite_bounded_and_ordered() {
+    for (delay, message) in &[
+        ("inf", "Delays are a number of seconds"),
+        ("NaN", "Delays are a number of seconds"),
+        ("10000000000000", "Delays can't be longer than a day"),
+        ("close=86401", "Delays can't be longer than a day"),
+        ("5-1", "Delay range minimum exceeds its maximum"),
+    ] {
+        let command = format!(
+            "signup rules add r if username contains a then close delay {}",
+            delay
+        );
+        assert!(error(&command).contains(message), "{}", command);
+    }
+    assert!(
+        parse_command("signup rules add r if username contains a then close delay 86400").is_ok()
+    );
+}
+
+#[test]
+fn rating_resets_take_a_known_perf() {
+    assert!(
+        parse_command("signup rules add r if username contains a then resetrating:blitz").is_ok()
+    );
+    assert!(
+        error("signup rules add r if username contains a then resetrating:blits")
+            .contains("Unknown rating `blits`, did you mean `blitz`?")
+    );
+}
+
+#[test]
+fn nodelay_and_delay_are_exclusive() {
+    for options in &[
+        "nodelay delay 5",
+        "delay 5 nodelay",
+        "delay close=5 nodelay",
+    ] {
+        let command = format!(
+            "signup rules add r if username contains a then close {}",
+            options
+        );
+        assert!(
+            error(&command).contains("`nodelay` and `delay` can't be used together"),
+            "{}",
+            command
+        );
+    }
+}
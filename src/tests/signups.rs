@@ -0,0 +1,772 @@
+use crate::discord::{DiscordWebhook, DiscordWebhookConfig};
+use crate::signup::rules::Endpoints;
+use crate::tests::mock::{MockLichess, MockSlack};
+use crate::tests::{leak, rule, signup_event, wait_for, Bot, Options, CHANNEL, NOTIFY_CHANNEL};
+use chrono::Utc;
+use hyper::Method;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+#[tokio::test]
+async fn matching_signup_triggers_actions() {
+    let bot = Bot::start(json!([rule(
+        "spam-mail",
+        json!({ "EmailContains": "spam.example" }),
+        json!(["Shadowban", "EngineMark"]),
+    )]))
+    .await;
+
+    bot.signup("Spammer1", "spammer1@spam.example", "10.0.0.1")
+        .await;
+
+    let mut paths: Vec<String> = bot
+        .lichess
+        .wait_for_actions(2)
+        .await
+        .iter()
+        .map(|a| {
+            assert_eq!(a.method, Method::POST);
+            assert_eq!(a.authorization, Some("Bearer test-token".to_owned()));
+            a.path.clone()
+        })
+        .collect();
+    paths.sort();
+    assert_eq!(
+        paths,
+        vec!["/mod/Spammer1/engine/true", "/mod/Spammer1/troll/true"]
+    );
+
+    let (channel, text) = bot.chat.wait_for_message("Rule spam-mail match").await;
+    assert_eq!(channel, CHANNEL);
+    assert!(text.contains(&format!("{}/@/Spammer1?mod", bot.lichess.url)));
+}
+
+#[tokio::test]
+async fn overridden_endpoints_are_used_for_actions() {
+    let bot = Options {
+        overrides: &[
+            ("shadowban", "{base}/api/mod/{username}/shadowban?v=1"),
+            ("close", "{base}/mod/close?user={username}"),
+        ],
+        ..Options::new(json!([rule(
+            "spam-mail",
+            json!({ "EmailContains": "spam.example" }),
+            json!(["Shadowban", "Close"]),
+        )]))
+    }
+    .start()
+    .await;
+
+    bot.signup("Spammer1", "spammer1@spam.example", "10.0.0.1")
+        .await;
+
+    let mut paths: Vec<String> = bot
+        .lichess
+        .wait_for_actions(2)
+        .await
+        .into_iter()
+        .map(|a| a.path)
+        .collect();
+    paths.sort();
+    assert_eq!(
+        paths,
+        vec![
+            "/api/mod/Spammer1/shadowban?v=1",
+            "/mod/close?user=Spammer1"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn only_matching_signups_trigger_actions() {
+    let bot = Bot::start(json!([rule(
+        "raid-ip",
+        json!({ "IpMatch": "10.6.6.6" }),
+        json!(["Shadowban"]),
+    )]))
+    .await;
+
+    bot.signup("Innocent", "innocent@example.com", "10.0.0.2")
+        .await;
+    bot.signup("Raider", "raider@example.com", "10.6.6.6").await;
+
+    bot.chat.wait_for_message("Rule raid-ip match").await;
+    let actions = bot.lichess.actions();
+    assert_eq!(actions.len(), 1);
+    assert_eq!(actions[0].path, "/mod/Raider/troll/true");
+}
+
+#[tokio::test]
+async fn notify_posts_to_notify_channel_only() {
+    let bot = Bot::start(json!([rule(
+        "watch",
+        json!({ "UsernameContains": "watch" }),
+        json!(["NotifySlack"]),
+    )]))
+    .await;
+
+    bot.signup("WatchMe", "watch@example.com", "10.0.0.3").await;
+
+    let (channel, _) = bot.chat.wait_for_message("Rule watch match").await;
+    assert_eq!(channel, NOTIFY_CHANNEL);
+    assert!(bot.lichess.actions().is_empty());
+}
+
+#[tokio::test]
+async fn note_action_sends_form_body() {
+    let bot = Bot::start(json!([rule(
+        "note-raid",
+        json!({ "UsernameContains": "raid" }),
+        json!([{ "ModNote": "raid wave" }]),
+    )]))
+    .await;
+
+    bot.signup("RaidBot", "raid@example.com", "10.0.0.4").await;
+
+    let actions = bot.lichess.wait_for_actions(1).await;
+    assert_eq!(actions[0].path, "/@/RaidBot/note");
+    assert!(actions[0]
+        .body
+        .starts_with("text=raid+wave+%28signup+rule+note-raid"));
+    assert!(actions[0].body.ends_with("&mod=true"));
+}
+
+#[tokio::test]
+async fn exemption_suppresses_actions() {
+    let bot = Options {
+        exemptions: json!([{
+            "name": "school",
+            "criterion": { "EmailContains": "@school.example" },
+        }]),
+        ..Options::new(json!([rule(
+            "school-ip",
+            json!({ "IpMatch": "10.1.1.1" }),
+            json!(["Shadowban"]),
+        )]))
+    }
+    .start()
+    .await;
+
+    bot.signup("Pupil", "pupil@school.example", "10.1.1.1")
+        .await;
+
+    let (_, text) = bot
+        .chat
+        .wait_for_message("Exemption school prevented")
+        .await;
+    assert!(text.contains("school-ip"));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(bot.lichess.actions().is_empty());
+}
+
+fn score_rules() -> serde_json::Value {
+    let mut mail = rule(
+        "mail",
+        json!({ "EmailContains": "raid.example" }),
+        json!([]),
+    );
+    mail["weight"] = json!(3);
+    let mut ip = rule("ip", json!({ "IpMatch": "10.6.6.6" }), json!([]));
+    ip["weight"] = json!(2);
+    json!([
+        mail,
+        rule(
+            "threshold",
+            json!({ "ScoreRange": [5, null] }),
+            json!(["Shadowban"]),
+        ),
+        ip,
+    ])
+}
+
+#[tokio::test]
+async fn score_rule_fires_when_weights_reach_threshold() {
+    let bot = Bot::start(score_rules()).await;
+
+    bot.signup("Raider", "raider@raid.example", "10.6.6.6")
+        .await;
+
+    assert_eq!(
+        bot.lichess.wait_for_actions(1).await[0].path,
+        "/mod/Raider/troll/true"
+    );
+    let (_, text) = bot.chat.wait_for_message("Rule threshold match").await;
+    assert!(text.contains("Score: 5 (mail +3, ip +2)."));
+    // Weighted rules without actions only show in the score.
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(bot
+        .chat
+        .messages()
+        .iter()
+        .all(|(_, text)| !text.contains("Rule mail match") && !text.contains("Rule ip match")));
+}
+
+#[tokio::test]
+async fn score_rule_does_not_fire_below_threshold() {
+    let bot = Bot::start(score_rules()).await;
+
+    bot.signup("Mailer", "mailer@raid.example", "10.0.0.1")
+        .await;
+    bot.signup("Raider", "raider@raid.example", "10.6.6.6")
+        .await;
+
+    // Signups are handled in order, so the first one is done once the second is acted on.
+    bot.chat.wait_for_message("Rule threshold match").await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let actions = bot.lichess.actions();
+    assert_eq!(actions.len(), 1);
+    assert_eq!(actions[0].path, "/mod/Raider/troll/true");
+}
+
+#[tokio::test]
+async fn score_rules_see_the_same_score_whatever_their_order() {
+    let mut mail = rule(
+        "mail",
+        json!({ "EmailContains": "raid.example" }),
+        json!([]),
+    );
+    mail["weight"] = json!(3);
+    let mut first = rule(
+        "first",
+        json!({ "ScoreRange": [3, 3] }),
+        json!(["Shadowban"]),
+    );
+    first["weight"] = json!(10);
+    let mut second = rule("second", json!({ "ScoreRange": [3, 3] }), json!(["Close"]));
+    second["weight"] = json!(10);
+
+    for rules in [json!([mail, first, second]), json!([second, first, mail])] {
+        let bot = Bot::start(rules).await;
+        bot.signup("Raider", "raider@raid.example", "10.0.0.1")
+            .await;
+
+        for name in ["first", "second"] {
+            let (_, text) = bot
+                .chat
+                .wait_for_message(&format!("Rule {} match", name))
+                .await;
+            assert!(text.contains("Score: 3 (mail +3)."));
+        }
+        assert_eq!(bot.lichess.wait_for_actions(2).await.len(), 2);
+    }
+}
+
+#[tokio::test]
+async fn events_split_across_chunks_are_joined() {
+    let bot = Bot::start(json!([rule(
+        "raid-ip",
+        json!({ "IpMatch": "10.6.6.6" }),
+        json!(["Shadowban"]),
+    )]))
+    .await;
+
+    let line = signup_event("Raider", "raider@example.com", "10.6.6.6") + "\n";
+    let (first, second) = line.split_at(line.len() / 2);
+    bot.lichess.push_chunk(first.to_owned()).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    bot.lichess.push_chunk(second.to_owned()).await;
+
+    assert_eq!(
+        bot.lichess.wait_for_actions(1).await[0].path,
+        "/mod/Raider/troll/true"
+    );
+}
+
+#[tokio::test]
+async fn stream_reconnects_after_disconnect() {
+    let bot = Bot::start(json!([rule(
+        "raid-ip",
+        json!({ "IpMatch": "10.6.6.6" }),
+        json!(["Shadowban"]),
+    )]))
+    .await;
+
+    bot.signup("Innocent", "innocent@example.com", "10.0.0.2")
+        .await;
+    bot.lichess.disconnect();
+    wait_for("the bot to reconnect to the event stream", || {
+        if bot.lichess.connections() > 1 {
+            Some(())
+        } else {
+            None
+        }
+    })
+    .await;
+
+    bot.signup("Raider", "raider@example.com", "10.6.6.6").await;
+    assert_eq!(
+        bot.lichess.wait_for_actions(1).await[0].path,
+        "/mod/Raider/troll/true"
+    );
+}
+
+#[tokio::test]
+async fn silent_stream_is_replaced_not_duplicated() {
+    let bot = Options {
+        stream_timeout: Duration::from_millis(500),
+        ..Options::new(json!([rule(
+            "raid-ip",
+            json!({ "IpMatch": "10.6.6.6" }),
+            json!(["Shadowban"]),
+        )]))
+    }
+    .start()
+    .await;
+
+    // Every restart leaves the bot reading one stream, however many there were.
+    wait_for("the silent stream to be replaced twice", || {
+        if bot.lichess.connections() >= 3 && bot.lichess.live_connections() == 1 {
+            Some(())
+        } else {
+            None
+        }
+    })
+    .await;
+    let (channel, _) = bot
+        .chat
+        .wait_for_message("Alert: no data on the lichess event stream")
+        .await;
+    assert_eq!(channel, NOTIFY_CHANNEL);
+
+    bot.signup("Raider", "raider@example.com", "10.6.6.6").await;
+    bot.lichess.wait_for_actions(1).await;
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(bot.lichess.actions().len(), 1);
+    assert_eq!(
+        bot.chat
+            .messages()
+            .iter()
+            .filter(|(_, text)| text.contains("Rule raid-ip match"))
+            .count(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn shutdown_saves_delayed_actions() {
+    let mut delayed = rule(
+        "slow-ban",
+        json!({ "IpMatch": "10.6.6.6" }),
+        json!(["Shadowban"]),
+    );
+    delayed["delay"] = json!({ "Fixed": 60000 });
+    let mut bot = Bot::start(json!([delayed])).await;
+
+    bot.signup("Raider", "raider@example.com", "10.6.6.6").await;
+    bot.chat.wait_for_message("Rule slow-ban match").await;
+
+    assert!(bot.shutdown().await);
+    bot.chat
+        .wait_for_message("Shutting down, 1 pending actions saved.")
+        .await;
+    assert!(bot.lichess.actions().is_empty());
+
+    let saved: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(bot.pending_path).unwrap()).unwrap();
+    assert_eq!(
+        saved[0]["endpoint"],
+        format!("{}/mod/Raider/troll/true", bot.lichess.url)
+    );
+}
+
+#[tokio::test]
+async fn saved_actions_are_sent_after_restart() {
+    let lichess = MockLichess::start().await;
+    let pending = json!([{
+        "action": "shadowban",
+        "endpoint": format!("{}/mod/Raider/troll/true", lichess.url),
+        "body": null,
+        "due": Utc::now(),
+    }]);
+    let bot = Options {
+        pending_actions: pending,
+        ..Options::new(json!([]))
+    }
+    .start_with(lichess, MockSlack::start().await)
+    .await;
+
+    assert_eq!(
+        bot.lichess.wait_for_actions(1).await[0].path,
+        "/mod/Raider/troll/true"
+    );
+    assert!(!Path::new(bot.pending_path).exists());
+}
+
+#[tokio::test]
+async fn duplicate_signup_events_are_dropped() {
+    let bot = Bot::start(json!([rule(
+        "raid-ip",
+        json!({ "IpMatch": "10.6.6.6" }),
+        json!(["Shadowban"]),
+    )]))
+    .await;
+
+    bot.signup("Raider", "raider@example.com", "10.6.6.6").await;
+    bot.signup("Raider", "raider@example.com", "10.6.6.6").await;
+    bot.signup("Raider2", "raider2@example.com", "10.6.6.6")
+        .await;
+
+    let paths: Vec<String> = bot
+        .lichess
+        .wait_for_actions(2)
+        .await
+        .into_iter()
+        .map(|a| a.path)
+        .collect();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(bot.lichess.actions().len(), 2);
+    assert!(paths.contains(&"/mod/Raider2/troll/true".to_owned()));
+}
+
+#[tokio::test]
+async fn matches_of_routed_rules_are_posted_to_discord() {
+    let bot = Bot::start(json!([
+        rule(
+            "discord-raid",
+            json!({ "UsernameContains": "dcraid" }),
+            json!(["Shadowban"]),
+        ),
+        rule(
+            "quiet-raid",
+            json!({ "UsernameContains": "dcraid" }),
+            json!(["Close"]),
+        ),
+    ]))
+    .await;
+
+    bot.signup("DcRaider", "dcraid@example.com", "10.0.0.5")
+        .await;
+
+    let embed = bot.discord.wait_for_embed("Rule discord-raid match").await;
+    let profile_url = format!("{}/@/DcRaider?mod", bot.lichess.url);
+    assert_eq!(embed["url"], profile_url.as_str());
+    assert_eq!(
+        embed["fields"][0]["value"],
+        format!("[DcRaider]({})", profile_url).as_str()
+    );
+    assert_eq!(embed["fields"][3]["value"], "shadowban");
+
+    bot.chat.wait_for_message("Rule quiet-raid match").await;
+    assert!(bot
+        .discord
+        .embeds()
+        .iter()
+        .all(|embed| !embed["title"].as_str().unwrap_or("").contains("quiet-raid")));
+}
+
+#[test]
+fn discord_webhook_urls_are_checked_on_start() {
+    let webhook = |url| {
+        DiscordWebhook::new(DiscordWebhookConfig {
+            url,
+            rules: None,
+            alerts: true,
+        })
+    };
+    assert!(webhook("https://discord.com/api/webhooks/1/token").is_ok());
+    assert!(webhook("discord.com/api/webhooks").is_err());
+    assert!(webhook("https://discord.com/api webhooks").is_err());
+}
+
+fn audit_trail(bot: &Bot) -> Vec<serde_json::Value> {
+    fs::read_to_string(bot.audit_path)
+        .unwrap_or_default()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+async fn wait_for_audit(bot: &Bot, event: &str, count: usize) {
+    wait_for(&format!("{} `{}` audit entries", count, event), || {
+        let entries = audit_trail(bot)
+            .into_iter()
+            .filter(|e| e["event"] == event)
+            .count();
+        if entries >= count {
+            Some(())
+        } else {
+            None
+        }
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn revert_undoes_sent_actions_and_cancels_pending_ones() {
+    let mut delayed = rule(
+        "slow-close",
+        json!({ "UsernameContains": "misfire" }),
+        json!(["Close"]),
+    );
+    delayed["delay"] = json!({ "Fixed": 60000 });
+    let bot = Bot::start(json!([
+        rule(
+            "misfire",
+            json!({ "UsernameContains": "misfire" }),
+            json!([
+                "Shadowban",
+                { "ReportBan": true },
+                { "Warn": "Warning: spam is not permitted" },
+            ]),
+        ),
+        delayed,
+    ]))
+    .await;
+
+    bot.signup("Misfired", "misfired@example.com", "10.0.0.9")
+        .await;
+    bot.lichess.wait_for_actions(3).await;
+    wait_for_audit(&bot, "sent", 3).await;
+
+    bot.command("signup revert misfired").await;
+    bot.chat
+        .wait_for_message("Cancelled 1 pending actions and reverting 2 sent actions")
+        .await;
+    bot.chat
+        .wait_for_message("Reverted 2 of 2 actions on")
+        .await;
+
+    let paths: Vec<String> = bot.lichess.actions()[3..]
+        .iter()
+        .map(|a| a.path.clone())
+        .collect();
+    assert!(paths.contains(&"/mod/Misfired/troll/false".to_owned()));
+    assert!(paths.contains(&"/mod/Misfired/reportban/false".to_owned()));
+    wait_for_audit(&bot, "reverted", 2).await;
+    wait_for_audit(&bot, "cancelled", 1).await;
+
+    bot.command("signup revert Misfired").await;
+    bot.chat.wait_for_message("Nothing to revert on").await;
+}
+
+#[tokio::test]
+async fn revert_rule_undoes_actions_since() {
+    let bot = Bot::start(json!([rule(
+        "wide-net",
+        json!({ "UsernameContains": "netted" }),
+        json!(["EngineMark"]),
+    )]))
+    .await;
+
+    bot.signup("Netted1", "netted1@example.com", "10.0.1.1")
+        .await;
+    bot.signup("Netted2", "netted2@example.com", "10.0.1.2")
+        .await;
+    wait_for_audit(&bot, "sent", 2).await;
+
+    bot.command("signup revert-rule wide-net --since 1h").await;
+    bot.chat
+        .wait_for_message("Reverted 2 of 2 actions on users caught by rule wide-net.")
+        .await;
+    let reverted: Vec<String> = bot.lichess.actions()[2..]
+        .iter()
+        .map(|a| a.path.clone())
+        .collect();
+    assert!(reverted.contains(&"/mod/Netted1/engine/false".to_owned()));
+    assert!(reverted.contains(&"/mod/Netted2/engine/false".to_owned()));
+}
+
+#[tokio::test]
+async fn renamed_rules_keep_their_pending_actions_audit_trail_and_matches() {
+    let mut old = rule(
+        "old-name",
+        json!({ "UsernameContains": "renamee" }),
+        json!(["Shadowban", "Close"]),
+    );
+    old["action_delays"] = json!([["close", { "Fixed": 60000 }]]);
+    let bot = Bot::start(json!([old])).await;
+
+    // More than the rule's recent matches, so the first one is only in the audit trail.
+    for i in 1..5 {
+        bot.signup(&format!("Renamee{}", i), "renamee@example.com", "10.0.1.4")
+            .await;
+    }
+    wait_for_audit(&bot, "sent", 4).await;
+
+    bot.command("signup rules edit old-name rename old-name")
+        .await;
+    bot.chat
+        .wait_for_message("Rule old-name edited: renamed from old-name to old-name.")
+        .await;
+    bot.command("signup rules edit old-name rename new-name")
+        .await;
+    bot.chat
+        .wait_for_message("Rule old-name edited: renamed from old-name to new-name.")
+        .await;
+
+    bot.command("signup rules false-positive new-name Renamee1")
+        .await;
+    bot.chat
+        .wait_for_message("by rule new-name as a false positive")
+        .await;
+
+    bot.command("signup revert-rule new-name").await;
+    bot.chat
+        .wait_for_message("Cancelled 4 pending actions and reverting 4 sent actions")
+        .await;
+    bot.chat
+        .wait_for_message("Reverted 4 of 4 actions on users caught by rule new-name.")
+        .await;
+}
+
+#[tokio::test]
+async fn overridden_revert_endpoints_are_used() {
+    let bot = Options {
+        overrides: &[
+            ("close", "{base}/api/mod/{username}/close"),
+            ("unclose", "{base}/api/mod/{username}/reopen"),
+        ],
+        ..Options::new(json!([rule(
+            "tripwire",
+            json!({ "UsernameContains": "tripped" }),
+            json!(["Close"]),
+        )]))
+    }
+    .start()
+    .await;
+
+    bot.signup("Tripped", "tripped@example.com", "10.0.1.3")
+        .await;
+    wait_for_audit(&bot, "sent", 1).await;
+
+    bot.command("signup revert Tripped").await;
+    bot.chat
+        .wait_for_message("Reverted 1 of 1 actions on")
+        .await;
+    let paths: Vec<String> = bot.lichess.actions().into_iter().map(|a| a.path).collect();
+    assert_eq!(
+        paths,
+        vec!["/api/mod/Tripped/close", "/api/mod/Tripped/reopen"]
+    );
+}
+
+#[tokio::test]
+async fn reverting_a_rule_keeps_the_same_action_of_other_rules_revertible() {
+    let bot = Bot::start(json!([
+        rule(
+            "first",
+            json!({ "UsernameContains": "twice" }),
+            json!(["Shadowban"]),
+        ),
+        rule(
+            "second",
+            json!({ "UsernameContains": "twice" }),
+            json!(["Shadowban"]),
+        ),
+    ]))
+    .await;
+
+    bot.signup("CaughtTwice", "twice@example.com", "10.0.1.5")
+        .await;
+    wait_for_audit(&bot, "sent", 2).await;
+
+    bot.command("signup revert-rule first").await;
+    bot.chat
+        .wait_for_message("Reverted 1 of 1 actions on users caught by rule first.")
+        .await;
+    bot.command("signup revert CaughtTwice").await;
+    bot.chat
+        .wait_for_message("Cancelled 0 pending actions and reverting 1 sent actions")
+        .await;
+    wait_for_audit(&bot, "reverted", 2).await;
+    let reverted: Vec<String> = audit_trail(&bot)
+        .into_iter()
+        .filter(|e| e["event"] == "reverted")
+        .map(|e| e["rule"].as_str().unwrap().to_owned())
+        .collect();
+    assert_eq!(reverted, vec!["first", "second"]);
+}
+
+#[tokio::test]
+async fn revert_reports_actions_still_being_sent() {
+    // Accepts requests and never answers them.
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let hanging = leak(format!(
+        "http://{}/{{username}}/close",
+        listener.local_addr().unwrap()
+    ));
+    let accepted = Arc::new(AtomicUsize::new(0));
+    let accepted2 = accepted.clone();
+    tokio::spawn(async move {
+        let mut sockets = vec![];
+        while let Ok((socket, _)) = listener.accept().await {
+            sockets.push(socket);
+            accepted2.fetch_add(1, Ordering::SeqCst);
+        }
+    });
+    let overrides: &'static [(&'static str, &'static str)] =
+        Box::leak(Box::new([("close", hanging)]));
+    let bot = Options {
+        overrides,
+        ..Options::new(json!([rule(
+            "slowpoke",
+            json!({ "UsernameContains": "slowpoke" }),
+            json!(["Shadowban", "Close"]),
+        )]))
+    }
+    .start()
+    .await;
+
+    bot.signup("Slowpoke", "slowpoke@example.com", "10.0.1.4")
+        .await;
+    wait_for_audit(&bot, "sent", 1).await;
+    wait_for("the close to be sent", || {
+        if accepted.load(Ordering::SeqCst) > 0 {
+            Some(())
+        } else {
+            None
+        }
+    })
+    .await;
+
+    bot.command("signup revert Slowpoke").await;
+    let (_, text) = bot
+        .chat
+        .wait_for_message("1 actions were being sent and were not reverted")
+        .await;
+    assert!(text.starts_with("Cancelled 0 pending actions and reverting 1 sent actions"));
+    bot.chat
+        .wait_for_message("Reverted 1 of 1 actions on")
+        .await;
+}
+
+#[test]
+fn only_revertible_actions_have_revert_overrides() {
+    let endpoints = |overrides| Endpoints {
+        base_url: "http://localhost",
+        overrides,
+    };
+    assert!(endpoints(&[("unclose", "{base}/reopen")])
+        .validate()
+        .is_ok());
+    assert!(endpoints(&[("unwarn", "{base}/unwarn")])
+        .validate()
+        .is_err());
+    assert!(endpoints(&[("unnotify", "{base}/unnotify")])
+        .validate()
+        .is_err());
+}
+
+#[tokio::test]
+async fn rating_reset_is_sent_and_cannot_be_reverted() {
+    let bot = Bot::start(json!([rule(
+        "farmer",
+        json!({ "UsernameContains": "farm" }),
+        json!([{ "ResetRating": "blitz" }]),
+    )]))
+    .await;
+
+    bot.signup("Farmer", "farmer@example.com", "10.0.2.1").await;
+    assert_eq!(
+        bot.lichess.wait_for_actions(1).await[0].path,
+        "/mod/Farmer/reset-rating/blitz"
+    );
+    wait_for_audit(&bot, "sent", 1).await;
+
+    bot.command("signup revert Farmer").await;
+    bot.chat.wait_for_message("Nothing to revert on").await;
+}
@@ -0,0 +1,71 @@
+use crate::slack::event::{EventCallback, EventsApiEvent};
+
+fn event(event: serde_json::Value) -> EventsApiEvent {
+    serde_json::from_value::<EventCallback>(json!({ "event": event }))
+        .unwrap()
+        .event
+}
+
+#[test]
+fn message_events_are_typed() {
+    let plain = event(json!({
+        "type": "message",
+        "user": "U00000001",
+        "text": "hello",
+        "channel": "CMODS",
+        "ts": "1540000000.000100",
+    }));
+    assert!(matches!(plain, EventsApiEvent::Message(ref m) if m.is_from_user()));
+
+    let reply = event(json!({
+        "type": "message",
+        "user": "U00000001",
+        "text": "in a thread",
+        "channel": "CMODS",
+        "ts": "1540000000.000200",
+        "thread_ts": "1540000000.000100",
+    }));
+    match reply {
+        EventsApiEvent::Message(m) => {
+            assert_eq!(m.thread_ts.as_deref(), Some("1540000000.000100"));
+            assert_eq!(m.reply_thread().as_deref(), Some("1540000000.000100"));
+        }
+        _ => panic!("not a message"),
+    }
+
+    let edited = event(json!({
+        "type": "message",
+        "subtype": "message_changed",
+        "channel": "CMODS",
+        "ts": "1540000000.000300",
+        "message": { "type": "message", "user": "U00000001", "text": "edited" },
+    }));
+    match edited {
+        EventsApiEvent::Message(m) => {
+            assert_eq!(m.subtype.as_deref(), Some("message_changed"));
+            assert!(!m.is_from_user());
+        }
+        _ => panic!("not a message"),
+    }
+
+    let bot = event(json!({
+        "type": "message",
+        "subtype": "bot_message",
+        "bot_id": "B00000001",
+        "text": "beep",
+        "channel": "CMODS",
+        "ts": "1540000000.000400",
+    }));
+    match bot {
+        EventsApiEvent::Message(m) => {
+            assert_eq!(m.bot_id.as_deref(), Some("B00000001"));
+            assert!(!m.is_from_user());
+        }
+        _ => panic!("not a message"),
+    }
+
+    assert!(matches!(
+        event(json!({ "type": "reaction_added", "user": "U00000001" })),
+        EventsApiEvent::Other
+    ));
+}
@@ -0,0 +1,88 @@
+use crate::tests::{rule, wait_for, Bot, WEBHOOK_SECRET};
+use crate::webhook::{signature, SIGNATURE_HEADER};
+use std::fs;
+
+fn payload(body: &str) -> serde_json::Value {
+    serde_json::from_str(body).unwrap()
+}
+
+#[tokio::test]
+async fn matches_actions_and_errors_are_signed_and_routed() {
+    let bot = Bot::start(json!([
+        rule(
+            "hook-raid",
+            json!({ "UsernameContains": "hookraid" }),
+            json!(["Shadowban"]),
+        ),
+        rule(
+            "hook-broken",
+            json!({ "Lua": "user:name():find('Hook') ~= nil and regex('', '(')" }),
+            json!(["Close"]),
+        ),
+        rule(
+            "other-raid",
+            json!({ "UsernameContains": "hookraid" }),
+            json!(["Close"]),
+        ),
+    ]))
+    .await;
+
+    bot.signup("HookRaider", "hookraid@example.com", "10.0.0.6")
+        .await;
+
+    let matched = bot.webhook.wait_for_attempts("match", 1).await;
+    let body = payload(&matched[0].body);
+    assert_eq!(body["rule"], "hook-raid");
+    assert_eq!(body["username"], "HookRaider");
+    assert_eq!(body["actions"], json!(["shadowban"]));
+    assert!(body["at"].is_string());
+    assert_eq!(
+        matched[0].headers[SIGNATURE_HEADER],
+        signature(WEBHOOK_SECRET, matched[0].body.as_bytes()).as_str()
+    );
+
+    let action = payload(&bot.webhook.wait_for_attempts("action", 1).await[0].body);
+    assert_eq!(action["rule"], "hook-raid");
+    assert_eq!(action["action"], "shadowban");
+    assert_eq!(action["ok"], true);
+
+    let error = payload(&bot.webhook.wait_for_attempts("error", 1).await[0].body);
+    assert_eq!(error["rule"], "hook-broken");
+    assert_eq!(error["username"], "HookRaider");
+
+    bot.chat.wait_for_message("Rule other-raid match").await;
+    assert!(bot
+        .webhook
+        .requests()
+        .iter()
+        .all(|req| payload(&req.body)["rule"] != "other-raid"));
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_retried_then_dead_lettered() {
+    let bot = Bot::start(json!([rule(
+        "hook-flaky",
+        json!({ "UsernameContains": "flaky" }),
+        json!([]),
+    )]))
+    .await;
+
+    bot.webhook.fail_next(2);
+    bot.signup("Flaky1", "flaky1@example.com", "10.0.0.7").await;
+    let attempts = bot.webhook.wait_for_attempts("match", 3).await;
+    assert_eq!(attempts[0].body, attempts[2].body);
+    assert!(fs::read_to_string(bot.dead_letter_path).is_err());
+
+    bot.webhook.fail_next(3);
+    bot.signup("Flaky2", "flaky2@example.com", "10.0.0.7").await;
+    let dead = wait_for("a dead letter", || {
+        fs::read_to_string(bot.dead_letter_path)
+            .ok()
+            .and_then(|s| s.lines().next().map(payload))
+    })
+    .await;
+    assert_eq!(dead["url"], bot.webhook.url.as_str());
+    assert_eq!(dead["payload"]["username"], "Flaky2");
+    assert!(dead["error"].as_str().unwrap().contains("500"));
+    assert_eq!(bot.webhook.wait_for_attempts("match", 6).await.len(), 6);
+}
@@ -0,0 +1,62 @@
+use crate::tests::mock::{MockLichess, MockZulip, ZULIP_BOT_NAME};
+use crate::tests::{rule, Options, CHANNEL};
+
+#[tokio::test]
+async fn commands_in_the_listen_stream_get_replies() {
+    let bot = Options::new(json!([rule(
+        "first",
+        json!({ "IpMatch": "10.0.0.1" }),
+        json!(["Shadowban"]),
+    )]))
+    .start_with(MockLichess::start().await, MockZulip::start().await)
+    .await;
+
+    bot.command("signup rules frobnicate").await;
+    let (stream, topic, _) = bot
+        .chat
+        .wait_for_message("Could not parse user command")
+        .await;
+    assert_eq!((stream.as_str(), topic.as_str()), (CHANNEL, "commands"));
+
+    bot.chat.send_message(
+        &format!("@**{}** signup rules list", ZULIP_BOT_NAME),
+        "other-stream",
+        "commands",
+    );
+    bot.chat
+        .send_message("signup rules list", CHANNEL, "commands");
+    bot.command("signup rules show first").await;
+    let (stream, topic, _) = bot
+        .chat
+        .wait_for_message("Criterion: IP equals `10.0.0.1`")
+        .await;
+    assert_eq!(
+        (stream.as_str(), topic.as_str()),
+        (CHANNEL, "lichess event stream")
+    );
+    assert!(bot
+        .chat
+        .messages()
+        .iter()
+        .all(|(_, _, content)| !content.starts_with("Current rules")));
+}
+
+#[tokio::test]
+async fn matches_are_posted_with_markdown_links() {
+    let bot = Options::new(json!([rule(
+        "zulip-raiders",
+        json!({ "UsernameContains": "zraider" }),
+        json!(["Shadowban"]),
+    )]))
+    .start_with(MockLichess::start().await, MockZulip::start().await)
+    .await;
+
+    bot.signup("TheZRaider", "zraider@example.com", "10.0.0.1")
+        .await;
+
+    let (_, _, content) = bot.chat.wait_for_message("Rule zulip-raiders match").await;
+    assert!(content.contains(&format!(
+        "[TheZRaider]({}/@/TheZRaider?mod)",
+        bot.lichess.url
+    )));
+}
@@ -0,0 +1,162 @@
+use crate::backoff::{Backoff, RetryPolicy};
+use crate::chat::{Notification, Sink};
+use crate::http;
+use crate::metrics;
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::prelude::*;
+use hmac::{Hmac, Mac};
+use http_body_util::Full;
+use hyper::header::CONTENT_TYPE;
+use hyper::Request;
+use regex::Regex;
+use sha2::Sha256;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::Mutex;
+use tokio::time::sleep;
+
+/// The header with the payload's signature, `sha256=` and the hex HMAC-SHA256 of the body.
+pub const SIGNATURE_HEADER: &str = "X-Signature-256";
+
+#[derive(Clone, Copy)]
+pub struct WebhookConfig {
+    pub url: &'static str,
+    /// The key payloads are signed with.
+    pub secret: &'static str,
+    /// Only notifications about rules whose name matches this regex, `None` for all.
+    /// Alerts are not about a rule and always pass.
+    pub rules: Option<&'static str>,
+    /// The notification kinds to send: `match`, `action`, `error` and `alert`.
+    pub kinds: &'static [&'static str],
+}
+
+/// How payloads are delivered to the webhooks.
+#[derive(Clone, Copy)]
+pub struct Delivery {
+    pub attempts: u32,
+    pub retry: RetryPolicy,
+    /// Where payloads that could not be delivered are appended, as JSON lines.
+    pub dead_letter_path: &'static str,
+}
+
+/// A webhook that gets notifications as signed JSON payloads.
+pub struct Webhook {
+    config: WebhookConfig,
+    rules: Option<Regex>,
+    delivery: Delivery,
+    /// Held while appending to the dead-letter file.
+    dead_letters: Mutex<()>,
+}
+
+#[derive(Serialize)]
+struct DeadLetter<'a> {
+    url: &'a str,
+    at: DateTime<Utc>,
+    error: String,
+    payload: &'a serde_json::Value,
+}
+
+/// The value of `SIGNATURE_HEADER` for `body`.
+pub fn signature(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+impl Webhook {
+    pub fn new(config: WebhookConfig, delivery: Delivery) -> Result<Webhook, regex::Error> {
+        Ok(Webhook {
+            config,
+            rules: match config.rules {
+                Some(rules) => Some(Regex::new(rules)?),
+                None => None,
+            },
+            delivery,
+            dead_letters: Mutex::new(()),
+        })
+    }
+
+    async fn send(&self, body: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let req = Request::post(self.config.url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(
+                SIGNATURE_HEADER,
+                signature(self.config.secret, body.as_bytes()),
+            )
+            .body(Full::new(Bytes::from(body.to_owned())))?;
+        let res = http::client().request(req).await?;
+        if !res.status().is_success() {
+            return Err(format!("Webhook responded with {}.", res.status()).into());
+        }
+        Ok(())
+    }
+
+    fn dead_letter(
+        &self,
+        payload: &serde_json::Value,
+        error: String,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut line = serde_json::to_string(&DeadLetter {
+            url: self.config.url,
+            at: Utc::now(),
+            error,
+            payload,
+        })?;
+        line.push('\n');
+
+        let _lock = self.dead_letters.lock().unwrap();
+        OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(self.delivery.dead_letter_path)?
+            .write_all(line.as_bytes())?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Sink for Webhook {
+    fn wants(&self, notification: &Notification) -> bool {
+        self.config.kinds.contains(&notification.kind())
+            && match (&self.rules, notification.rule()) {
+                (Some(rules), Some(rule)) => rules.is_match(rule),
+                _ => true,
+            }
+    }
+
+    async fn notify(&self, notification: &Notification) {
+        let mut payload = json!(notification);
+        payload["at"] = json!(Utc::now());
+        let body = payload.to_string();
+
+        let mut backoff = Backoff::retrying(self.delivery.retry);
+        let mut attempt = 1;
+        loop {
+            let err = match self.send(&body).await {
+                Ok(_) => return,
+                Err(err) => err,
+            };
+            if attempt >= self.delivery.attempts {
+                metrics::WEBHOOK_DEAD_LETTERS.inc();
+                error!(url = self.config.url, %err, "Webhook failed, saving payload.");
+                if let Err(err) = self.dead_letter(&payload, err.to_string()) {
+                    error!(%err, "Error on saving undelivered webhook payload.");
+                }
+                return;
+            }
+
+            let delay = backoff.next_retry_delay();
+            warn!(
+                url = self.config.url,
+                %err,
+                attempt,
+                "Webhook failed, retrying in {:.1} seconds.",
+                delay.as_secs_f64()
+            );
+            sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
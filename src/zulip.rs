@@ -0,0 +1,217 @@
+use crate::chat::{Backend, Command, Listener};
+use crate::http;
+use crate::metrics;
+use async_trait::async_trait;
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE};
+use hyper::Request;
+use regex::Regex;
+use url::form_urlencoded;
+
+/// The topic for messages to a channel that names only a stream.
+const DEFAULT_TOPIC: &str = "lichess event stream";
+
+#[derive(Clone, Copy)]
+pub struct ZulipConfig {
+    /// Like `https://lichess.zulipchat.com`.
+    pub site: &'static str,
+    pub bot_email: &'static str,
+    pub api_key: &'static str,
+    /// The bot's full name, as in `@**Full Name**` mentions.
+    pub bot_name: &'static str,
+}
+
+/// Zulip, through its REST API. Commands are read from the event queue, by long polling.
+pub struct Zulip {
+    config: ZulipConfig,
+}
+
+#[derive(Deserialize)]
+struct Registered {
+    queue_id: String,
+    last_event_id: i64,
+}
+
+#[derive(Deserialize)]
+struct Events {
+    events: Vec<QueueEvent>,
+}
+
+#[derive(Deserialize)]
+struct QueueEvent {
+    id: i64,
+    message: Option<ZulipMessage>,
+}
+
+#[derive(Deserialize)]
+struct ZulipMessage {
+    #[serde(rename = "type")]
+    type_: String,
+    /// The stream name, for stream messages.
+    display_recipient: serde_json::Value,
+    subject: String,
+    content: String,
+    sender_email: String,
+}
+
+/// Converts Slack's `<url|text>` links to Markdown.
+fn markdown(text: &str) -> String {
+    lazy_static! {
+        static ref LINK: Regex = Regex::new(r"<(https?://[^|>]+)\|([^>]+)>").unwrap();
+    }
+    LINK.replace_all(text, "[$2]($1)").into_owned()
+}
+
+/// Splits a channel into its stream and topic.
+fn destination(channel: &str) -> (&str, &str) {
+    let mut parts = channel.splitn(2, '>');
+    let stream = parts.next().unwrap_or("");
+    (stream, parts.next().unwrap_or(DEFAULT_TOPIC))
+}
+
+impl Zulip {
+    pub fn new(config: ZulipConfig) -> Zulip {
+        Zulip { config }
+    }
+
+    fn url(&self, endpoint: &str) -> String {
+        format!(
+            "{}/api/v1/{}",
+            self.config.site.trim_end_matches('/'),
+            endpoint
+        )
+    }
+
+    fn authorization(&self) -> String {
+        format!(
+            "Basic {}",
+            base64::encode(format!("{}:{}", self.config.bot_email, self.config.api_key))
+        )
+    }
+
+    /// Sends a request and returns the JSON response, or an error for anything but
+    /// `"result": "success"`.
+    async fn call(
+        &self,
+        req: Request<Full<Bytes>>,
+    ) -> Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>> {
+        let res = http::client().request(req).await?;
+        let body = res.into_body().collect().await?.to_bytes();
+        let resp: serde_json::Value = serde_json::from_slice(&body)?;
+        if resp["result"] != "success" {
+            return Err(format!("Zulip API error: {}", String::from_utf8_lossy(&body)).into());
+        }
+        Ok(resp)
+    }
+
+    async fn post(
+        &self,
+        endpoint: &str,
+        form: &[(&str, &str)],
+    ) -> Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>> {
+        let body = form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(form)
+            .finish();
+        let req = Request::post(self.url(endpoint))
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .header(AUTHORIZATION, self.authorization())
+            .body(Full::new(Bytes::from(body)))?;
+        self.call(req).await
+    }
+
+    async fn send_to(&self, text: String, stream: &str, topic: &str) {
+        let form = [
+            ("type", "stream"),
+            ("to", stream),
+            ("topic", topic),
+            ("content", &markdown(&text)),
+        ];
+        if let Err(err) = self.post("messages", &form).await {
+            metrics::CHAT_POST_FAILURES.inc();
+            error!(stream, topic, %err, "Error on posting Zulip message.");
+        }
+    }
+
+    /// The command in `message`, if it was sent in the listen stream, mentioning the bot first.
+    fn command(&self, message: ZulipMessage, listener: &Listener) -> Option<Command> {
+        let (listen_stream, _) = destination(listener.channel);
+        let stream = message.display_recipient.as_str()?;
+        if message.type_ != "stream"
+            || stream != listen_stream
+            || message.sender_email == self.config.bot_email
+        {
+            return None;
+        }
+
+        let mention = message.content.strip_prefix("@**")?;
+        let end = mention.find("**")?;
+        let name = mention[..end].split('|').next().unwrap_or("");
+        if name != self.config.bot_name {
+            return None;
+        }
+
+        Some(Command {
+            author: message.sender_email,
+            channel: stream.to_owned(),
+            thread: Some(message.subject),
+            text: mention[end + 2..].trim().to_owned(),
+        })
+    }
+}
+
+#[async_trait]
+impl Backend for Zulip {
+    async fn send_message(&self, text: String, channel: &str) {
+        let (stream, topic) = destination(channel);
+        self.send_to(text, stream, topic).await
+    }
+
+    async fn reply(&self, text: String, command: &Command) {
+        let topic = command.thread.as_deref().unwrap_or(DEFAULT_TOPIC);
+        self.send_to(text, &command.channel, topic).await
+    }
+
+    async fn listen(
+        &self,
+        listener: &Listener,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let registered: Registered = serde_json::from_value(
+            self.post(
+                "register",
+                &[
+                    ("event_types", "[\"message\"]"),
+                    ("apply_markdown", "false"),
+                ],
+            )
+            .await?,
+        )?;
+        listener.connected()?;
+
+        let mut last_event_id = registered.last_event_id;
+        loop {
+            let query = form_urlencoded::Serializer::new(String::new())
+                .append_pair("queue_id", &registered.queue_id)
+                .append_pair("last_event_id", &last_event_id.to_string())
+                .finish();
+            let req = Request::get(format!("{}?{}", self.url("events"), query))
+                .header(AUTHORIZATION, self.authorization())
+                .body(Full::new(Bytes::new()))?;
+            // Zulip sends a heartbeat event at least every minute.
+            let events: Events = serde_json::from_value(self.call(req).await?)?;
+            listener.ping()?;
+
+            for event in events.events {
+                last_event_id = last_event_id.max(event.id);
+                let command = event
+                    .message
+                    .and_then(|message| self.command(message, listener));
+                if let Some(command) = command {
+                    if let Some(reply) = listener.command(&command) {
+                        self.reply(reply, &command).await;
+                    }
+                }
+            }
+        }
+    }
+}